@@ -9,7 +9,21 @@ import 'prismjs/components/prism-javascript';
 import 'prismjs/themes/prism.css';
 
 // Import WASM
-import init, { YellowWebInterpreter } from './pkg/the_yellow_compiler';
+import init, { YellowWebInterpreter, format_code } from './pkg/the_yellow_compiler';
+
+// Bytecode instructions executed between repaints.
+const INSTRUCTIONS_PER_SLICE = 5000;
+
+// Where the session survives page reloads.
+const SESSION_KEY = 'yellow-session';
+
+const saveSession = (interpreter) => {
+  try {
+    localStorage.setItem(SESSION_KEY, interpreter.snapshot());
+  } catch (e) {
+    console.warn("The session could not be preserved:", e);
+  }
+};
 
 function MinecraftEditor({ interpreter, onReset }) {
   const [code, setCode] = useState(
@@ -20,6 +34,40 @@ echo(truth);`
   const [output, setOutput] = useState("The void awaits your command...");
   const [sanity, setSanity] = useState(100);
   const [showGlitch, setShowGlitch] = useState(false);
+  const [checkpoint, setCheckpoint] = useState(null);
+
+  // A restored session arrives with its sanity already spent.
+  useEffect(() => {
+    if (interpreter) setSanity(interpreter.get_sanity());
+  }, [interpreter]);
+
+  const takeCheckpoint = () => {
+    try {
+      setCheckpoint(interpreter.snapshot());
+      setOutput("Checkpoint sealed. Reality may be restored to this moment.");
+    } catch (e) {
+      setOutput("Runtime Horror: " + e);
+    }
+  };
+
+  const rollBack = () => {
+    try {
+      interpreter.restore(checkpoint);
+      setSanity(interpreter.get_sanity());
+      setOutput("Reality rolls back to the checkpoint...");
+      saveSession(interpreter);
+    } catch (e) {
+      setOutput("Runtime Horror: " + e);
+    }
+  };
+
+  const tidyPages = () => {
+    try {
+      setCode(format_code(code));
+    } catch (e) {
+      setOutput("The pages resist tidying: " + e);
+    }
+  };
 
   const runCode = () => {
     if (!interpreter) {
@@ -27,19 +75,40 @@ echo(truth);`
       return;
     }
     
+    // Run in slices so long programs never freeze the page.
+    const runSlice = () => {
+      try {
+        const status = interpreter.step(INSTRUCTIONS_PER_SLICE);
+        setOutput(interpreter.get_output());
+
+        const newSanity = interpreter.get_sanity();
+        setSanity(newSanity);
+
+        const events = JSON.parse(interpreter.take_events());
+        const phantomSpawned = events.some(e => e.kind === "phantom_spawned");
+
+        if (status === "running") {
+          setTimeout(runSlice, 0);
+        } else {
+          saveSession(interpreter);
+        }
+
+        // The Crossroads Glitch, and a flicker whenever a phantom manifests
+        const crossroads = status !== "running" && newSanity < 20 && Math.random() > 0.7;
+        if (phantomSpawned || crossroads) {
+          setShowGlitch(true);
+          setTimeout(() => setShowGlitch(false), 200);
+        }
+      } catch (e) {
+        console.error(e);
+        setOutput("Runtime Horror: " + e);
+      }
+    };
+
     try {
       console.log("Executing Code:\n", code);
-      const result = interpreter.run_code(code);
-      setOutput(result);
-
-      const newSanity = interpreter.get_sanity();
-      setSanity(newSanity);
-
-      // The Crossroads Glitch
-      if (newSanity < 20 && Math.random() > 0.7) {
-        setShowGlitch(true);
-        setTimeout(() => setShowGlitch(false), 200);
-      }
+      interpreter.start(code);
+      runSlice();
     } catch (e) {
       console.error(e);
       setOutput("Runtime Horror: " + e);
@@ -267,6 +336,31 @@ echo(truth);`
         >
           SIGN
         </button>
+
+        {interpreter && (
+          <div style={{ display: 'flex', gap: '10px', justifyContent: 'center' }}>
+            <button
+              style={{ ...styles.button, marginTop: '10px', padding: '8px 20px', fontSize: '0.8rem' }}
+              onClick={tidyPages}
+            >
+              TIDY PAGES
+            </button>
+            <button
+              style={{ ...styles.button, marginTop: '10px', padding: '8px 20px', fontSize: '0.8rem' }}
+              onClick={takeCheckpoint}
+            >
+              CHECKPOINT
+            </button>
+            {checkpoint && (
+              <button
+                style={{ ...styles.button, marginTop: '10px', padding: '8px 20px', fontSize: '0.8rem' }}
+                onClick={rollBack}
+              >
+                ROLL BACK
+              </button>
+            )}
+          </div>
+        )}
         
         {sanity < 10 && (
           <button
@@ -410,7 +504,16 @@ export default function App() {
     const loadWasm = async () => {
       try {
         await init();
-        const interp = new YellowWebInterpreter();
+        const interp = YellowWebInterpreter.with_seed(BigInt(Date.now()));
+        const saved = localStorage.getItem(SESSION_KEY);
+        if (saved) {
+          try {
+            interp.restore(saved);
+          } catch (e) {
+            console.warn("The saved session has decayed:", e);
+            localStorage.removeItem(SESSION_KEY);
+          }
+        }
         setInterpreter(interp);
         console.log("The Yellow Interpreter has been summoned.");
       } catch (err) {
@@ -421,7 +524,8 @@ export default function App() {
   }, []);
 
   const resetInterpreter = () => {
-    const interp = new YellowWebInterpreter();
+    localStorage.removeItem(SESSION_KEY);
+    const interp = YellowWebInterpreter.with_seed(BigInt(Date.now()));
     setInterpreter(interp);
   };
 
@@ -0,0 +1,67 @@
+// ============================================================================
+// THE YELLOW LANGUAGE SERVER
+// ============================================================================
+//
+// Speaks the Language Server Protocol over standard input and output, for
+// desktop editors. Each message is framed by a `Content-Length` header; what
+// the messages mean is the library's `LanguageServer`'s business.
+
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+
+use the_yellow_compiler::LanguageServer;
+
+/// The next message body, or `None` once the client has hung up.
+fn read_message(input: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).map_err(|e| format!("Cannot read a header: {}", e))? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>().map_err(|_| format!("Bad Content-Length '{}'", value.trim()))?);
+            }
+        }
+    }
+    let length = length.ok_or("A message arrived without a Content-Length")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|e| format!("Cannot read a message: {}", e))?;
+    String::from_utf8(body).map(Some).map_err(|_| "A message is not UTF-8".to_string())
+}
+
+fn write_message(output: &mut impl Write, body: &str) -> std::io::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn serve() -> Result<i32, String> {
+    let mut server = LanguageServer::new();
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply).map_err(|e| format!("Cannot write a message: {}", e))?;
+        }
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+    // The client went away without saying goodbye.
+    Ok(1)
+}
+
+fn main() -> ExitCode {
+    match serve() {
+        Ok(code) => ExitCode::from(code as u8),
+        Err(e) => {
+            eprintln!("yellow-lsp: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
@@ -0,0 +1,61 @@
+// ============================================================================
+// CHECK
+// ============================================================================
+//
+// `yellow check`: reports what is wrong with each file without running it,
+// one `file:line:column: severity: message` a line, so editors and CI can
+// point at the place. Only errors fail the check; warnings are advice.
+
+use the_yellow_compiler::{Severity, YellowWebInterpreter};
+
+const USAGE: &str = "\
+usage: yellow check [--policy <name>] [file...]
+
+Reports undefined names, calls with the wrong number of arguments, code after
+Carcosa, manifests of fragments never remembered and whispers that will not
+run, without running anything. Reads standard input when no file is given.
+
+options:
+  --policy <name>   parse as the gentle, standard or nightmare policy would";
+
+/// Runs `yellow check` with the arguments after `check`. Returns whether every
+/// file is free of errors.
+pub(crate) fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut interpreter = YellowWebInterpreter::new();
+    let mut files = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            }
+            "--policy" => {
+                let policy = args.next().ok_or("--policy needs a value")?;
+                interpreter.set_sanity_policy(&policy)?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{}'", flag)),
+            file => files.push(file.to_string()),
+        }
+    }
+    if files.is_empty() {
+        files.push("-".to_string());
+    }
+
+    let mut clean = true;
+    for file in &files {
+        let source = crate::read_source(file)?;
+        for diagnostic in interpreter.diagnostics(&source) {
+            clean &= diagnostic.severity != Severity::Error;
+            println!(
+                "{}:{}:{}: {}: {}",
+                file,
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.severity.name(),
+                diagnostic.message
+            );
+        }
+    }
+    Ok(clean)
+}
@@ -0,0 +1,79 @@
+// ============================================================================
+// FMT
+// ============================================================================
+//
+// `yellow fmt`: lays files out canonically, comments kept. Prints to standard
+// output unless told to rewrite the files or only check them.
+
+use std::io::Write;
+
+use the_yellow_compiler::format_code;
+
+const USAGE: &str = "\
+usage: yellow fmt [--write | --check] [file...]
+
+Prints each file in canonical layout, or standard input when no file is given.
+
+options:
+  --write   rewrite the files in place
+  --check   list the files that are not formatted, changing nothing";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Print,
+    Write,
+    Check,
+}
+
+/// Runs `yellow fmt` with the arguments after `fmt`. Returns whether every file
+/// parsed and, when checking, was already formatted.
+pub(crate) fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut mode = Mode::Print;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            }
+            "--write" => mode = Mode::Write,
+            "--check" => mode = Mode::Check,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{}'", flag)),
+            file => files.push(file.to_string()),
+        }
+    }
+    if files.is_empty() {
+        if mode != Mode::Print {
+            return Err("--write and --check need files".to_string());
+        }
+        files.push("-".to_string());
+    }
+
+    let mut clean = true;
+    for file in &files {
+        let source = crate::read_source(file)?;
+        let formatted = match format_code(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                clean = false;
+                continue;
+            }
+        };
+        match mode {
+            Mode::Print => {
+                let _ = std::io::stdout().write_all(formatted.as_bytes());
+            }
+            Mode::Write if formatted != source => {
+                std::fs::write(file, formatted).map_err(|e| format!("Cannot write {}: {}", file, e))?;
+            }
+            Mode::Write => {}
+            Mode::Check if formatted != source => {
+                println!("{}", file);
+                clean = false;
+            }
+            Mode::Check => {}
+        }
+    }
+    Ok(clean)
+}
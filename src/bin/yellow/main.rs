@@ -0,0 +1,218 @@
+// ============================================================================
+// THE YELLOW COMMAND
+// ============================================================================
+//
+// Runs .yellow files from a terminal or CI, with no browser involved, or an
+// interactive session when no file is given. The exit status says how a run
+// ended: 0 when it completed, 1 when it ended in a parse error, a runtime
+// horror, depleted sanity or spent fuel, and 2 when the command itself was wrong.
+
+mod check;
+mod fmt;
+mod repl;
+mod test;
+
+use std::io::{IsTerminal, Read, Write};
+use std::process::ExitCode;
+
+use the_yellow_compiler::YellowWebInterpreter;
+
+use repl::Repl;
+
+const USAGE: &str = "\
+usage: yellow [options] [file]
+       yellow fmt [--write | --check] [file...]
+       yellow check [file...]
+       yellow test [--bless] [path...]
+
+Runs a .yellow file, or standard input when the file is '-'. With no file,
+starts an interactive session that keeps its state from one entry to the next.
+`yellow fmt --help` describes the formatter, `yellow check --help` the
+checker, which finds mistakes without running anything, and `yellow test --help`
+the golden-file test runner.
+
+options:
+  --seed <n>         seed the RNG (default 123456789)
+  --policy <name>    sanity policy: gentle, standard or nightmare
+  --engine <name>    tree (default) or bytecode
+  --lucid            keep counting sanity but suppress every horror effect
+  --fuel <n>         stop after <n> statements
+  --record <trace>   write a trace of the run to <trace>
+  --replay <trace>   replay a recorded trace instead of running a file
+  --color <when>     auto (default), always or never
+  -h, --help         show this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Colour {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone)]
+struct Options {
+    file: Option<String>,
+    seed: Option<u64>,
+    policy: Option<String>,
+    engine: Option<String>,
+    lucid: bool,
+    fuel: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    colour: Colour,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        file: None,
+        seed: None,
+        policy: None,
+        engine: None,
+        lucid: false,
+        fuel: None,
+        record: None,
+        replay: None,
+        colour: Colour::Auto,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--seed" => {
+                let seed = value("--seed")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("'{}' is not a seed", seed))?);
+            }
+            "--policy" => options.policy = Some(value("--policy")?),
+            "--engine" => options.engine = Some(value("--engine")?),
+            "--lucid" => options.lucid = true,
+            "--fuel" => {
+                let fuel = value("--fuel")?;
+                options.fuel = Some(fuel.parse().map_err(|_| format!("'{}' is not a statement count", fuel))?);
+            }
+            "--record" => options.record = Some(value("--record")?),
+            "--replay" => options.replay = Some(value("--replay")?),
+            "--color" | "--colour" => {
+                options.colour = match value("--color")?.as_str() {
+                    "auto" => Colour::Auto,
+                    "always" => Colour::Always,
+                    "never" => Colour::Never,
+                    other => return Err(format!("Unknown colour mode '{}'", other)),
+                }
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{}'", flag)),
+            file => {
+                if options.file.is_some() {
+                    return Err(format!("Unexpected argument '{}'", file));
+                }
+                options.file = Some(file.to_string());
+            }
+        }
+    }
+    match (&options.file, &options.replay) {
+        (None, None) if options.record.is_some() => Err("--record needs a file to run".to_string()),
+        (Some(_), Some(_)) => Err("--replay runs the trace's own source; give no file".to_string()),
+        _ => Ok(options),
+    }
+}
+
+fn read_source(file: &str) -> Result<String, String> {
+    if file == "-" {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source).map_err(|e| format!("Cannot read standard input: {}", e))?;
+        Ok(source)
+    } else {
+        std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))
+    }
+}
+
+/// A fresh interpreter set up as the options ask.
+fn session(options: &Options) -> Result<YellowWebInterpreter, String> {
+    let mut interpreter = match options.seed {
+        Some(seed) => YellowWebInterpreter::with_seed(seed),
+        None => YellowWebInterpreter::new(),
+    };
+    if let Some(policy) = &options.policy {
+        interpreter.set_sanity_policy(policy)?;
+    }
+    if let Some(engine) = &options.engine {
+        interpreter.set_engine(engine)?;
+    }
+    interpreter.set_lucid(options.lucid);
+    interpreter.set_fuel(options.fuel);
+    interpreter.set_recording(options.record.is_some());
+    Ok(interpreter)
+}
+
+fn colour(options: &Options) -> bool {
+    match options.colour {
+        Colour::Always => true,
+        Colour::Never => false,
+        Colour::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// Runs what the options ask for, returning whether the run completed.
+fn run(options: &Options) -> Result<bool, String> {
+    let mut interpreter = session(options)?;
+    if let Some(trace) = &options.replay {
+        let trace = std::fs::read_to_string(trace).map_err(|e| format!("Cannot read {}: {}", trace, e))?;
+        interpreter.replay(&trace)?;
+    } else if let Some(file) = &options.file {
+        interpreter.run_code(&read_source(file)?);
+    } else {
+        let settings = options.clone();
+        Repl::new(interpreter, Box::new(move || session(&settings)), colour(options)).run()?;
+        return Ok(true);
+    }
+
+    let output = if colour(options) { interpreter.get_output_ansi() } else { interpreter.get_output() };
+    let mut stdout = std::io::stdout().lock();
+    // A closed pipe (`yellow x.yellow | head`) is not the program's fault.
+    let _ = stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush());
+
+    if let Some(path) = &options.record {
+        let trace = interpreter.get_trace();
+        if trace != "null" {
+            std::fs::write(path, trace).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+        }
+    }
+    Ok(interpreter.get_status() == "finished")
+}
+
+/// How a subcommand's outcome maps onto the exit status.
+fn exit(outcome: Result<bool, String>) -> ExitCode {
+    match outcome {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("yellow: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "fmt") {
+        return exit(fmt::run(args.split_off(1)));
+    }
+    if args.first().is_some_and(|arg| arg == "check") {
+        return exit(check::run(args.split_off(1)));
+    }
+    if args.first().is_some_and(|arg| arg == "test") {
+        return exit(test::run(args.split_off(1)));
+    }
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("yellow: {}", e);
+            eprintln!("Try 'yellow --help'.");
+            return ExitCode::from(2);
+        }
+    };
+    exit(run(&options))
+}
@@ -0,0 +1,142 @@
+// ============================================================================
+// REPL
+// ============================================================================
+//
+// An interactive session against one interpreter, so acts, variables and the
+// damage done to sanity carry over from one entry to the next. Input is read
+// until its braces balance; lines starting with ':' are commands.
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use the_yellow_compiler::YellowWebInterpreter;
+
+const HELP: &str = "\
+Enter Yellow statements; a block runs once its braces close.
+
+commands:
+  :sanity       sanity, entropy and whether reality is anchored
+  :vars         global variables
+  :infections   infected variables
+  :echoes       temporal echoes, oldest first
+  :fragments    remembered fragments
+  :phantoms     phantom variables
+  :anchor       start over with a fresh session (also :reset)
+  :help         show this help
+  :quit         leave (also Ctrl-D)";
+
+const PROMPT: &str = "yellow> ";
+const CONTINUE: &str = "   ...> ";
+
+/// How many `{` are still open at the end of `source`, and whether a string is. Braces
+/// inside strings and `#` comments do not count.
+fn unbalanced(source: &str) -> (i64, bool) {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    for c in source.chars() {
+        match c {
+            '\n' => in_comment = false,
+            _ if in_comment => {}
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '#' => in_comment = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    (depth, in_string)
+}
+
+/// Whether `source` is ready to run rather than waiting for the rest of a block.
+fn complete(source: &str) -> bool {
+    let (depth, in_string) = unbalanced(source);
+    depth <= 0 && !in_string
+}
+
+pub(crate) struct Repl {
+    interpreter: YellowWebInterpreter,
+    /// Builds the interpreter `:anchor` starts over with.
+    fresh: Box<dyn Fn() -> Result<YellowWebInterpreter, String>>,
+    colour: bool,
+}
+
+impl Repl {
+    pub(crate) fn new(
+        mut interpreter: YellowWebInterpreter,
+        fresh: Box<dyn Fn() -> Result<YellowWebInterpreter, String>>,
+        colour: bool,
+    ) -> Self {
+        interpreter.set_quiet(true);
+        Repl { interpreter, fresh, colour }
+    }
+
+    pub(crate) fn run(&mut self) -> Result<(), String> {
+        let mut editor = DefaultEditor::new().map_err(|e| format!("Cannot open the terminal: {}", e))?;
+        println!("The Yellow Compiler. Type :help for commands, :quit to leave.");
+        let mut pending = String::new();
+        loop {
+            let prompt = if pending.is_empty() { PROMPT } else { CONTINUE };
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    if pending.is_empty() && line.trim_start().starts_with(':') {
+                        let _ = editor.add_history_entry(line.as_str());
+                        if !self.command(line.trim())? {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                    pending.push_str(&line);
+                    pending.push('\n');
+                    if complete(&pending) {
+                        let entry = std::mem::take(&mut pending);
+                        if !entry.trim().is_empty() {
+                            let _ = editor.add_history_entry(entry.trim_end());
+                            self.execute(&entry);
+                        }
+                    }
+                }
+                // Ctrl-C abandons the entry being typed.
+                Err(ReadlineError::Interrupted) => pending.clear(),
+                Err(ReadlineError::Eof) => return Ok(()),
+                Err(e) => return Err(format!("Cannot read input: {}", e)),
+            }
+        }
+    }
+
+    fn execute(&mut self, source: &str) {
+        self.interpreter.run_code(source);
+        let output = if self.colour { self.interpreter.get_output_ansi() } else { self.interpreter.get_output() };
+        print!("{}", output);
+    }
+
+    /// Runs a `:command`. False once the session should end.
+    fn command(&mut self, line: &str) -> Result<bool, String> {
+        let topic = match line {
+            ":quit" | ":q" | ":exit" => return Ok(false),
+            ":help" | ":h" => {
+                println!("{}", HELP);
+                return Ok(true);
+            }
+            ":anchor" | ":reset" => {
+                self.interpreter = (self.fresh)()?;
+                self.interpreter.set_quiet(true);
+                println!("Reality is anchored. Sanity restored to {:.1}%.", self.interpreter.get_sanity());
+                return Ok(true);
+            }
+            ":sanity" => "sanity",
+            ":vars" | ":variables" => "variables",
+            ":infections" => "infections",
+            ":echoes" => "echoes",
+            ":fragments" => "fragments",
+            ":phantoms" => "phantoms",
+            other => {
+                println!("Unknown command '{}'. Type :help for the list.", other);
+                return Ok(true);
+            }
+        };
+        print!("{}", self.interpreter.report(topic)?);
+        Ok(true)
+    }
+}
@@ -0,0 +1,91 @@
+// ============================================================================
+// TEST
+// ============================================================================
+//
+// `yellow test`: runs golden programs, each `.yellow` file against the
+// `.out` and `.sanity` files beside it, and reports what no longer matches.
+// `--bless` records what the programs do now as the new expectations. Each
+// program's trials are run too, with the sanity each one cost.
+
+use std::path::PathBuf;
+
+use the_yellow_compiler::{Golden, Verdict};
+
+const USAGE: &str = "\
+usage: yellow test [--bless] [path...]
+
+Runs every .yellow file under the given files and directories (the current
+directory by default) with a fixed seed on both engines, and compares its
+output with name.out and its final sanity with name.sanity. Programs in a
+directory named lucid run in lucid mode. Every trial in a program then runs
+in a fresh interpreter and must get through its body.
+
+options:
+  --bless   write the expectations from this run instead of comparing";
+
+/// Runs `yellow test` with the arguments after `test`. Returns whether every
+/// program passed.
+pub(crate) fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut bless = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            }
+            "--bless" => bless = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{}'", flag)),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let goldens = Golden::discover(&paths)?;
+    if goldens.is_empty() {
+        return Err("No .yellow files found".to_string());
+    }
+    let (mut passed, mut blessed, mut failed) = (0, 0, 0);
+    let (mut trials_passed, mut trials_failed) = (0, 0);
+    for golden in &goldens {
+        let name = golden.source.display();
+        match golden.run(bless)? {
+            Verdict::Passed => {
+                println!("ok       {}", name);
+                passed += 1;
+            }
+            Verdict::Blessed => {
+                println!("blessed  {}", name);
+                blessed += 1;
+            }
+            Verdict::Failed(reason) => {
+                println!("FAILED   {}", name);
+                for line in reason.lines() {
+                    println!("    {}", line);
+                }
+                failed += 1;
+            }
+        }
+        for trial in golden.trials()? {
+            match &trial.failure {
+                None => {
+                    println!("  trial '{}' ... ok ({:.2}% sanity)", trial.name, trial.sanity_consumed);
+                    trials_passed += 1;
+                }
+                Some(failure) => {
+                    println!("  trial '{}' ... FAILED ({:.2}% sanity)", trial.name, trial.sanity_consumed);
+                    println!("      {}", failure);
+                    trials_failed += 1;
+                }
+            }
+        }
+    }
+    println!();
+    println!("{} passed, {} blessed, {} failed", passed, blessed, failed);
+    if trials_passed + trials_failed > 0 {
+        println!("trials: {} passed, {} failed", trials_passed, trials_failed);
+    }
+    Ok(failed == 0 && trials_failed == 0)
+}
@@ -0,0 +1,204 @@
+// ============================================================================
+// BUILT-IN ACTS
+// ============================================================================
+//
+// The acts every program starts with, written in Rust. They are installed
+// into the globals like any act a program defines, so a program can mask
+// over them or forget them, and both engines call them the same way. Views
+// of memory, snapshots and infections pass over the ones still under their
+// own names: they belong to the language, not to the program. Acts the host
+// registers (see `host.rs`) are natives too, and are treated the same way.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::events::Events;
+use crate::host::HostAct;
+use crate::{shown, Interpreter, QuantumState, SanityCost, Value};
+
+type Run = fn(&mut Interpreter, Vec<Value>, &mut Events) -> Result<Value, String>;
+
+/// An act implemented natively. What it runs stays inside the interpreter.
+pub struct Native {
+    name: String,
+    arity: usize,
+    body: Body,
+}
+
+enum Body {
+    Builtin(Run),
+    /// Registered by the embedding program, which sees only the arguments.
+    Host(HostAct),
+}
+
+impl Native {
+    /// The name it was installed under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How many arguments it takes.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub(crate) fn host(name: &str, arity: usize, act: HostAct) -> Self {
+        Native { name: name.to_string(), arity, body: Body::Host(act) }
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}/{}>", self.name, self.arity)
+    }
+}
+
+struct Builtin {
+    name: &'static str,
+    params: &'static [&'static str],
+    /// What kind of value it gives back, as the language server describes values.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    returns: &'static str,
+    run: Run,
+}
+
+const BUILTINS: [Builtin; 10] = [
+    Builtin { name: "length", params: &["text"], returns: "number", run: length },
+    Builtin { name: "text", params: &["value"], returns: "string", run: text },
+    Builtin { name: "floor", params: &["n"], returns: "number", run: floor },
+    Builtin { name: "round", params: &["n"], returns: "number", run: round },
+    Builtin { name: "random", params: &[], returns: "number", run: random },
+    Builtin { name: "min", params: &["a", "b"], returns: "number", run: min },
+    Builtin { name: "max", params: &["a", "b"], returns: "number", run: max },
+    Builtin { name: "type_of", params: &["value"], returns: "string", run: type_of },
+    Builtin { name: "substring", params: &["text", "start", "end"], returns: "string", run: substring },
+    Builtin { name: "true_name", params: &[], returns: "string", run: true_name },
+];
+
+/// The globals a fresh interpreter starts with.
+pub(crate) fn globals() -> BTreeMap<String, Value> {
+    BUILTINS
+        .iter()
+        .map(|builtin| {
+            let native =
+                Native { name: builtin.name.to_string(), arity: builtin.params.len(), body: Body::Builtin(builtin.run) };
+            (builtin.name.to_string(), Value::Native(Rc::new(native)))
+        })
+        .collect()
+}
+
+/// The built-in `name` as a value, for snapshots that hold one under another name.
+pub(crate) fn lookup(name: &str) -> Option<Value> {
+    globals().remove(name)
+}
+
+/// `(name, params, returns)` for every built-in, for the language server.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn signatures() -> impl Iterator<Item = (&'static str, &'static [&'static str], &'static str)> {
+    BUILTINS.iter().map(|builtin| (builtin.name, builtin.params, builtin.returns))
+}
+
+/// Whether `val`, held under `name`, is a native still under its own name.
+pub(crate) fn installed(name: &str, val: &Value) -> bool {
+    matches!(val, Value::Native(native) if native.name() == name)
+}
+
+impl Interpreter {
+    /// The globals the program made, leaving out the natives it started with.
+    pub(crate) fn own_globals(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.global_env.iter().filter(|(name, val)| !installed(name, val))
+    }
+
+    /// The natives still under their own names: the built-in acts and any the host registered.
+    pub(crate) fn natives(&self) -> BTreeMap<String, Value> {
+        self.global_env.iter().filter(|(name, val)| installed(name, val)).map(|(n, v)| (n.clone(), v.clone())).collect()
+    }
+
+    /// Calls a native whose arity has been checked, with its arguments evaluated.
+    pub(crate) fn call_native(&mut self, native: &Native, args: Vec<Value>, buffer: &mut Events) -> Result<Value, String> {
+        match &native.body {
+            Body::Builtin(run) => run(self, args, buffer),
+            Body::Host(act) => act(&args),
+        }
+    }
+}
+
+fn number(act: &str, val: &Value) -> Result<f64, String> {
+    match val {
+        Value::Number(n) => Ok(*n),
+        val => Err(format!("{} wants a number, not {}", act, shown(val))),
+    }
+}
+
+fn string<'a>(act: &str, val: &'a Value) -> Result<&'a str, String> {
+    match val {
+        Value::String(s) => Ok(s),
+        val => Err(format!("{} wants a string, not {}", act, shown(val))),
+    }
+}
+
+fn length(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::Number(string("length", &args[0])?.chars().count() as f64))
+}
+
+fn text(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+fn floor(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::Number(number("floor", &args[0])?.floor()))
+}
+
+fn round(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::Number(number("round", &args[0])?.round()))
+}
+
+/// A draw from the interpreter's own generator, so seeds, recordings and replays cover it.
+/// Reading the omens is not free.
+fn random(interp: &mut Interpreter, _: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    interp.drain_sanity(SanityCost::Omen);
+    Ok(Value::Number(interp.pseudo_random()))
+}
+
+fn min(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::Number(number("min", &args[0])?.min(number("min", &args[1])?)))
+}
+
+fn max(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    Ok(Value::Number(number("max", &args[0])?.max(number("max", &args[1])?)))
+}
+
+fn type_of(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    let kind = match &args[0] {
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Boolean(_) => "boolean",
+        Value::Null => "pallid",
+        Value::Function { .. } | Value::Native(_) => "act",
+        Value::History(_) => "history",
+        Value::Quantum(qs) => match **qs {
+            QuantumState::Superposition(_) => "superposition",
+            QuantumState::Entangled(_) => "entangled",
+            QuantumState::Phantom => "phantom",
+        },
+    };
+    Ok(Value::String(kind.to_string()))
+}
+
+/// The characters from `start` up to, not including, `end`. Both are floored and
+/// clamped to the text, so the answer is empty rather than a horror when they cross.
+fn substring(_: &mut Interpreter, args: Vec<Value>, _: &mut Events) -> Result<Value, String> {
+    let chars: Vec<char> = string("substring", &args[0])?.chars().collect();
+    let clamp = |n: f64| if n.is_nan() { 0 } else { n.floor().clamp(0.0, chars.len() as f64) as usize };
+    let start = clamp(number("substring", &args[1])?);
+    let end = clamp(number("substring", &args[2])?);
+    Ok(Value::String(chars[start..end.max(start)].iter().collect()))
+}
+
+/// Asking for the King's true name is answered, at a price.
+fn true_name(interp: &mut Interpreter, _: Vec<Value>, buffer: &mut Events) -> Result<Value, String> {
+    interp.drain_sanity(SanityCost::TrueName);
+    buffer.warn("⚠ The name is spoken, and something in the dark turns to listen.");
+    Ok(Value::String("Hastur".to_string()))
+}
@@ -0,0 +1,421 @@
+// ============================================================================
+// CHECKER
+// ============================================================================
+//
+// Finds a program's mistakes without running it, so nothing costs sanity or
+// leaves echoes behind. The resolver already knows which names are read
+// before anything defines them; this pass adds the rest: calls with the wrong
+// number of arguments, statements stranded after `Carcosa`, fragments that
+// are manifested but never remembered, and whispers that will not run.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::json::{self, Object};
+use crate::{
+    resolver, Expr, Interpreter, Lexer, Parser, Span, Stmt, StmtKind, Value, MAX_WHISPER_LENGTH, MAX_WHISPER_STATEMENTS,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The program will fail here.
+    Error,
+    /// The program runs, but not as written.
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// One finding, placed at the start of the statement it concerns (or, for a
+/// parse error, the token the parser stopped at).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Counted from 1.
+    pub line: u32,
+    /// Counted from 1.
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(span: Span, message: String) -> Self {
+        Diagnostic { line: span.line, column: span.column, severity: Severity::Error, message }
+    }
+
+    fn warning(span: Span, message: String) -> Self {
+        Diagnostic { line: span.line, column: span.column, severity: Severity::Warning, message }
+    }
+
+    fn to_json(&self) -> String {
+        Object::new()
+            .raw("line", &self.line.to_string())
+            .raw("column", &self.column.to_string())
+            .string("severity", self.severity.name())
+            .string("message", &self.message)
+            .finish()
+    }
+}
+
+/// `[{"line", "column", "severity", "message"}]`, in source order.
+pub(crate) fn to_json(diagnostics: &[Diagnostic]) -> String {
+    json::array(diagnostics.iter().map(Diagnostic::to_json))
+}
+
+struct Checker<'a> {
+    interp: &'a Interpreter,
+    /// How many arguments each act takes, for names that only ever hold one act.
+    arities: BTreeMap<String, usize>,
+    /// Names that only ever hold plain values, so calling them cannot work.
+    values: BTreeSet<String>,
+    /// Names fragments are remembered under, by the program, its whispers or earlier runs.
+    remembered: BTreeSet<String>,
+    /// The statement being checked.
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Interpreter {
+    /// Everything wrong with `source` that can be seen without running it, in source
+    /// order. Names and fragments left by earlier runs count as defined.
+    pub(crate) fn check(&self, source: &str) -> Vec<Diagnostic> {
+        let mut parser = Parser::with_policy(Lexer::new(source).tokenize(), self.policy.as_ref());
+        let mut program = match parser.parse_program() {
+            Ok(program) => program,
+            Err(e) => return vec![Diagnostic::error(parser.span(), e)],
+        };
+        let mut diagnostics: Vec<_> = resolver::resolve(&mut program, &self.global_env)
+            .into_iter()
+            .map(|(span, message)| Diagnostic::warning(span, message))
+            .collect();
+
+        let mut checker = Checker {
+            interp: self,
+            arities: BTreeMap::new(),
+            values: BTreeSet::new(),
+            remembered: self.memory_fragments.keys().cloned().collect(),
+            span: Span::default(),
+            diagnostics: Vec::new(),
+        };
+        checker.survey(&program);
+        checker.block(&program);
+        diagnostics.append(&mut checker.diagnostics);
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        diagnostics
+    }
+}
+
+impl Checker<'_> {
+    /// Collects the acts the program defines and the names it remembers, before any
+    /// statement is checked, since acts may be called from bodies defined earlier.
+    fn survey(&mut self, program: &[Stmt]) {
+        let mut acts: BTreeMap<String, Option<usize>> = BTreeMap::new();
+        for (name, val) in &self.interp.global_env {
+            match val {
+                Value::Function { act, .. } => {
+                    acts.insert(name.clone(), Some(act.arity));
+                }
+                Value::Native(native) => {
+                    acts.insert(name.clone(), Some(native.arity()));
+                }
+                _ => {
+                    self.values.insert(name.clone());
+                }
+            }
+        }
+        let mut maybe_acts = BTreeSet::new();
+        let mut defined_here = BTreeSet::new();
+        let mut reassigned = BTreeSet::new();
+        survey(program, &mut |stmt| match &stmt.kind {
+            StmtKind::Act { name, act } => {
+                // A name bound to acts of different arities cannot be checked.
+                let arity = Some(act.arity);
+                if defined_here.insert(name.clone()) {
+                    acts.insert(name.clone(), arity);
+                } else if acts.get(name) != Some(&arity) {
+                    acts.insert(name.clone(), None);
+                }
+                maybe_acts.insert(name.clone());
+                maybe_acts.extend(act.params().iter().cloned());
+                reassigned.extend(act.params().iter().cloned());
+            }
+            StmtKind::Mask { name, value, .. } => {
+                reassigned.insert(name.clone());
+                if matches!(value, Expr::Number(_) | Expr::String(_) | Expr::Boolean(_)) {
+                    self.values.insert(name.clone());
+                } else {
+                    maybe_acts.insert(name.clone());
+                }
+            }
+            StmtKind::Rewrite { target: name } => {
+                reassigned.insert(name.clone());
+                maybe_acts.insert(name.clone());
+            }
+            StmtKind::Remember { name, .. } => {
+                self.remembered.insert(name.clone());
+            }
+            StmtKind::Whisper(code) => {
+                if let Ok(whispered) = Parser::with_policy(Lexer::new(code).tokenize(), self.interp.policy.as_ref()).parse_program() {
+                    survey(&whispered, &mut |stmt| {
+                        if let StmtKind::Remember { name, .. } = &stmt.kind {
+                            self.remembered.insert(name.clone());
+                        }
+                    });
+                }
+            }
+            _ => {}
+        });
+        self.values.retain(|name| !maybe_acts.contains(name) && !acts.contains_key(name));
+        self.arities = acts
+            .into_iter()
+            .filter(|(name, _)| !reassigned.contains(name))
+            .filter_map(|(name, arity)| Some((name, arity?)))
+            .collect();
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        let mut ended = None;
+        for stmt in stmts {
+            if let Some(line) = ended.take() {
+                self.diagnostics.push(Diagnostic::warning(
+                    stmt.span,
+                    format!("Unreachable: the Carcosa on line {} leaves this block first", line),
+                ));
+            }
+            self.statement(stmt);
+            if matches!(stmt.kind, StmtKind::Carcosa(_)) {
+                ended = Some(stmt.span.line);
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        self.span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Mask { value: expr, .. }
+            | StmtKind::Echo(expr)
+            | StmtKind::ExprStmt(expr)
+            | StmtKind::Assert(expr)
+            | StmtKind::Carcosa(Some(expr))
+            | StmtKind::Remember { value: Some(expr), .. } => self.expression(expr),
+            StmtKind::Scene { body, .. } | StmtKind::Trial { body, .. } => self.block(body),
+            StmtKind::Hastur { condition, body, .. } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            StmtKind::Cassilda { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            StmtKind::Act { act, .. } => self.block(&act.body),
+            StmtKind::Whisper(code) => self.whisper(code),
+            StmtKind::Carcosa(None)
+            | StmtKind::Rewrite { .. }
+            | StmtKind::Remember { value: None, .. }
+            | StmtKind::Purge(_)
+            | StmtKind::Forget(_)
+            | StmtKind::Infect(_)
+            | StmtKind::Anchor => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Call { name, args } => {
+                for arg in args {
+                    self.expression(arg);
+                }
+                if self.values.contains(name) {
+                    self.diagnostics.push(Diagnostic::error(
+                        self.span,
+                        format!("Not an act: '{}' only ever holds values, so it cannot be called", name),
+                    ));
+                } else if let Some(&arity) = self.arities.get(name) {
+                    if arity != args.len() {
+                        self.diagnostics.push(Diagnostic::error(
+                            self.span,
+                            format!("Arity mismatch: '{}' takes {} but is given {}", name, plural(arity), args.len()),
+                        ));
+                    }
+                }
+            }
+            Expr::Manifest(name) | Expr::Unearth(name) => {
+                if !self.remembered.contains(name) {
+                    let verb = if matches!(expr, Expr::Manifest(_)) { "manifest" } else { "unearth" };
+                    self.diagnostics.push(Diagnostic::warning(
+                        self.span,
+                        format!("{}({}) can only find pallid: '{}' is never remembered", verb, name, name),
+                    ));
+                }
+            }
+            Expr::BinaryOp { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Superpose(exprs) => {
+                for e in exprs {
+                    self.expression(e);
+                }
+            }
+            Expr::Rewrite { target: expr }
+            | Expr::Collapse(expr)
+            | Expr::Rift(expr)
+            | Expr::Before(_, expr)
+            | Expr::During(_, expr) => self.expression(expr),
+            Expr::Number(_)
+            | Expr::String(_)
+            | Expr::Boolean(_)
+            | Expr::Identifier(..)
+            | Expr::Entangle(..)
+            | Expr::Echoes(_)
+            | Expr::Peek(_)
+            | Expr::Oldest(_)
+            | Expr::Fragments(_) => {}
+        }
+    }
+
+    /// The reasons the interpreter would refuse `code`, in the order it checks them.
+    fn whisper(&mut self, code: &str) {
+        if code.len() > MAX_WHISPER_LENGTH {
+            self.diagnostics.push(Diagnostic::warning(
+                self.span,
+                format!("Whisper is longer than {} characters and will not run", MAX_WHISPER_LENGTH),
+            ));
+            return;
+        }
+        let lowered = code.to_lowercase();
+        if let Some(pattern) = self.interp.forbidden_patterns.iter().find(|p| lowered.contains(&p.to_lowercase())) {
+            self.diagnostics.push(Diagnostic::warning(
+                self.span,
+                format!("Whisper holds the forbidden incantation '{}' and will not run", pattern),
+            ));
+            return;
+        }
+        let mut parser = Parser::with_policy(Lexer::new(code).tokenize(), self.interp.policy.as_ref());
+        match parser.parse_program() {
+            Ok(program) if program.len() > MAX_WHISPER_STATEMENTS => self.diagnostics.push(Diagnostic::warning(
+                self.span,
+                format!("Whisper holds more than {} statements and will not run", MAX_WHISPER_STATEMENTS),
+            )),
+            Ok(_) => {}
+            Err(e) => self.diagnostics.push(Diagnostic::error(
+                self.span,
+                format!("Whisper does not parse: {} at column {}", e, parser.span().column),
+            )),
+        }
+    }
+}
+
+/// Calls `visit` on every statement in `stmts`, blocks and act bodies included.
+fn survey(stmts: &[Stmt], visit: &mut impl FnMut(&Stmt)) {
+    for stmt in stmts {
+        visit(stmt);
+        match &stmt.kind {
+            StmtKind::Scene { body, .. } | StmtKind::Hastur { body, .. } | StmtKind::Trial { body, .. } => {
+                survey(body, visit)
+            }
+            StmtKind::Cassilda { then_branch, else_branch, .. } => {
+                survey(then_branch, visit);
+                if let Some(else_branch) = else_branch {
+                    survey(else_branch, visit);
+                }
+            }
+            StmtKind::Act { act, .. } => survey(&act.body, visit),
+            _ => {}
+        }
+    }
+}
+
+fn plural(arguments: usize) -> String {
+    if arguments == 1 { "1 argument".to_string() } else { format!("{} arguments", arguments) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(source: &str) -> Vec<(u32, Severity, String)> {
+        Interpreter::new().check(source).into_iter().map(|d| (d.line, d.severity, d.message)).collect()
+    }
+
+    #[test]
+    fn a_clean_program_has_nothing_to_say() {
+        assert_eq!(findings("act f(n) {\n    Carcosa n;\n}\nmask a -> f(1);\nremember a;\necho(manifest(a));\n"), []);
+    }
+
+    #[test]
+    fn wrong_argument_counts_are_errors() {
+        let source = "act f(n) {\n    Carcosa n;\n}\necho(f(1, 2));\necho(length());\n";
+        assert_eq!(
+            findings(source),
+            [
+                (4, Severity::Error, "Arity mismatch: 'f' takes 1 argument but is given 2".to_string()),
+                (5, Severity::Error, "Arity mismatch: 'length' takes 1 argument but is given 0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn calling_a_value_is_an_error() {
+        assert_eq!(
+            findings("mask x -> 1;\nx();\nmask g -> 2;\nact g() {\n    Carcosa 3;\n}\necho(g());\n"),
+            [(2, Severity::Error, "Not an act: 'x' only ever holds values, so it cannot be called".to_string())]
+        );
+    }
+
+    #[test]
+    fn resolver_findings_are_warnings() {
+        assert_eq!(
+            findings("echo(ghost);\n"),
+            [(1, Severity::Warning, "Undefined: 'ghost' is read before anything masks it".to_string())]
+        );
+    }
+
+    #[test]
+    fn unreachable_code_and_empty_memories_are_warnings() {
+        let source = "act f() {\n    Carcosa 1;\n    echo(2);\n}\necho(manifest(m));\necho(unearth(m));\n";
+        assert_eq!(
+            findings(source),
+            [
+                (3, Severity::Warning, "Unreachable: the Carcosa on line 2 leaves this block first".to_string()),
+                (5, Severity::Warning, "manifest(m) can only find pallid: 'm' is never remembered".to_string()),
+                (6, Severity::Warning, "unearth(m) can only find pallid: 'm' is never remembered".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn whispers_that_will_not_run() {
+        let long = format!("whisper \"{}\";\n", "echo(1);".repeat(MAX_WHISPER_LENGTH / 8 + 1));
+        let many = format!("whisper \"{}\";\n", "echo(1);".repeat(MAX_WHISPER_STATEMENTS + 1));
+        let source = format!("{}{}whisper \"rift(1);\";\nwhisper \"echo(;\";\n", long, many);
+        let found = findings(&source);
+        let reasons: Vec<_> =
+            found.iter().map(|(line, severity, message)| (*line, *severity, &message[..17])).collect();
+        assert_eq!(
+            reasons,
+            [
+                (1, Severity::Warning, "Whisper is longer"),
+                (2, Severity::Warning, "Whisper holds mor"),
+                (3, Severity::Warning, "Whisper holds the"),
+                (4, Severity::Error, "Whisper does not "),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_parse_error_is_the_only_finding() {
+        let found = findings("mask a -> ;\necho(ghost);\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].0, found[0].1), (1, Severity::Error));
+    }
+}
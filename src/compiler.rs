@@ -0,0 +1,414 @@
+// ============================================================================
+// BYTECODE COMPILER
+// ============================================================================
+//
+// Lowers the AST to a flat instruction stream for the VM in `vm.rs`.
+// Operands are indices into the chunk's pools, so every instruction stays
+// small and nothing in the program tree is cloned while it runs.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{printer, ActDef, BinaryOperator, Expr, Slot, Stmt, StmtKind, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    // Statement framing: `Enter` charges the statement and opens a depth level, `Leave` closes it.
+    Enter,
+    Leave,
+    Pop,
+    Return,
+    Jump(u32),
+
+    // Statements
+    Mask(u32),
+    /// Masks into slot `.0` of the innermost frame; `.1` names the variable.
+    MaskLocal(u32, u32),
+    Echo,
+    /// Pushes a scene frame with the layout at this index.
+    PushScope(u32),
+    PopScope,
+    /// Starts a Hastur (`false`) or rift (`true`) loop.
+    LoopEnter(bool),
+    /// Leaves to the target once a rift has used up its paradox iterations.
+    LoopGuard(u32),
+    /// Pops the condition and leaves to the target when the loop should stop.
+    LoopTest(u32),
+    /// Charges the finished iteration, then jumps back to the target or breaks out.
+    LoopNext(u32),
+    LoopExit,
+    /// Pops a Cassilda condition and jumps to the target when the branch is not taken.
+    Branch(u32),
+    Act(u32),
+    Rewrite(u32),
+    Remember(u32),
+    RememberValue(u32),
+    Purge(u32),
+    Forget(u32),
+    Infect(u32),
+    /// `.0` is the whispered source, a string constant.
+    Whisper(u32),
+    Anchor,
+    /// Pops a condition and fails unless it holds; `.0` is its source text, a string constant.
+    Assert(u32),
+    /// Pops two operands, compares them and fails, showing both, unless the result holds.
+    AssertBinary(BinaryOperator, u32),
+
+    // Expressions
+    Number(u32),
+    Constant(u32),
+    Null,
+    Load(u32),
+    LoadLocal(u32, u32),
+    Binary(BinaryOperator),
+    /// Resolves the act `name` expecting `argc` arguments, before they are evaluated.
+    Callee(u32, u32),
+    Call(u32),
+    Mutate,
+    Superpose(u32),
+    Collapse,
+    Manifest(u32),
+    Peek(u32),
+    Oldest(u32),
+    Unearth(u32),
+    Fragments(u32),
+    Entangle(u32, u32),
+    Rift,
+    Before(u32),
+    During(u32),
+    Echoes(u32),
+}
+
+/// An act statement: the name it defines and the act, which compiles its body when first called.
+#[derive(Debug)]
+pub(crate) struct ActProto {
+    pub(crate) name: u32,
+    pub(crate) act: Rc<ActDef>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Chunk {
+    pub(crate) code: Vec<Op>,
+    /// Source line of the statement each instruction belongs to.
+    pub(crate) lines: Vec<u32>,
+    /// Names of variables, acts and fragments, each once.
+    pub(crate) names: Vec<String>,
+    pub(crate) numbers: Vec<f64>,
+    /// Literal values, and the text of whispers and asserts as strings.
+    pub(crate) constants: Vec<Value>,
+    pub(crate) acts: Vec<ActProto>,
+    pub(crate) layouts: Vec<Rc<[String]>>,
+}
+
+impl Chunk {
+    /// The string constant at `idx`.
+    pub(crate) fn text(&self, idx: u32) -> &str {
+        match &self.constants[idx as usize] {
+            Value::String(text) => text,
+            val => unreachable!("constant {} is {:?}, not text", idx, val),
+        }
+    }
+}
+
+pub(crate) fn compile(program: &[Stmt]) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.block(program);
+    compiler.finish()
+}
+
+#[derive(Default)]
+struct Compiler {
+    chunk: Chunk,
+    interned: BTreeMap<String, u32>,
+    /// Line of the statement being compiled.
+    line: u32,
+}
+
+impl Compiler {
+    fn finish(self) -> Chunk {
+        self.chunk
+    }
+
+    fn emit(&mut self, op: Op) -> u32 {
+        self.chunk.code.push(op);
+        self.chunk.lines.push(self.line);
+        (self.chunk.code.len() - 1) as u32
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Points the jump emitted at `at` to the current position.
+    fn patch(&mut self, at: u32) {
+        let target = self.here();
+        self.chunk.code[at as usize] = match self.chunk.code[at as usize] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::LoopGuard(_) => Op::LoopGuard(target),
+            Op::LoopTest(_) => Op::LoopTest(target),
+            Op::Branch(_) => Op::Branch(target),
+            op => op,
+        };
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(&idx) = self.interned.get(name) {
+            return idx;
+        }
+        let idx = self.chunk.names.len() as u32;
+        self.chunk.names.push(name.to_string());
+        self.interned.insert(name.to_string(), idx);
+        idx
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        self.chunk.constants.push(value);
+        (self.chunk.constants.len() - 1) as u32
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        let outer = self.line;
+        for stmt in statements {
+            self.line = stmt.span.line;
+            self.emit(Op::Enter);
+            self.statement(stmt);
+            self.emit(Op::Leave);
+        }
+        self.line = outer;
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Mask { name, slot, value } => {
+                self.expression(value);
+                let name = self.name(name);
+                match slot {
+                    Slot::Local(idx) => self.emit(Op::MaskLocal(*idx, name)),
+                    Slot::Dynamic => self.emit(Op::Mask(name)),
+                };
+            }
+            StmtKind::Echo(expr) => {
+                self.expression(expr);
+                self.emit(Op::Echo);
+            }
+            StmtKind::Scene { body, locals } => {
+                self.chunk.layouts.push(locals.clone());
+                let layout = (self.chunk.layouts.len() - 1) as u32;
+                self.emit(Op::PushScope(layout));
+                self.block(body);
+                self.emit(Op::PopScope);
+            }
+            StmtKind::Hastur { condition, body, is_rift } => {
+                self.emit(Op::LoopEnter(*is_rift));
+                let top = self.here();
+                let guard = self.emit(Op::LoopGuard(0));
+                self.expression(condition);
+                let test = self.emit(Op::LoopTest(0));
+                self.block(body);
+                self.emit(Op::LoopNext(top));
+                self.patch(guard);
+                self.patch(test);
+                self.emit(Op::LoopExit);
+            }
+            StmtKind::Cassilda { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                let branch = self.emit(Op::Branch(0));
+                self.block(then_branch);
+                let skip_else = self.emit(Op::Jump(0));
+                self.patch(branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+                self.patch(skip_else);
+            }
+            StmtKind::Carcosa(expr) => {
+                match expr {
+                    Some(expr) => self.expression(expr),
+                    None => {
+                        self.emit(Op::Null);
+                    }
+                }
+                self.emit(Op::Return);
+            }
+            StmtKind::Act { name, act } => {
+                let proto = ActProto { name: self.name(name), act: act.clone() };
+                self.chunk.acts.push(proto);
+                let idx = (self.chunk.acts.len() - 1) as u32;
+                self.emit(Op::Act(idx));
+            }
+            StmtKind::Rewrite { target } => {
+                let target = self.name(target);
+                self.emit(Op::Rewrite(target));
+            }
+            StmtKind::Remember { name, value } => {
+                let op = match value {
+                    Some(expr) => {
+                        self.expression(expr);
+                        Op::RememberValue(self.name(name))
+                    }
+                    None => Op::Remember(self.name(name)),
+                };
+                self.emit(op);
+            }
+            StmtKind::Purge(name) => {
+                let name = self.name(name);
+                self.emit(Op::Purge(name));
+            }
+            StmtKind::Forget(name) => {
+                let name = self.name(name);
+                self.emit(Op::Forget(name));
+            }
+            StmtKind::Infect(name) => {
+                let name = self.name(name);
+                self.emit(Op::Infect(name));
+            }
+            StmtKind::Whisper(code) => {
+                let code = self.constant(Value::String(code.clone()));
+                self.emit(Op::Whisper(code));
+            }
+            StmtKind::Anchor => {
+                self.emit(Op::Anchor);
+            }
+            StmtKind::Assert(condition) => {
+                let text = self.constant(Value::String(printer::expression(condition)));
+                match condition {
+                    Expr::BinaryOp { left, op, right } => {
+                        self.expression(left);
+                        self.expression(right);
+                        self.emit(Op::AssertBinary(*op, text));
+                    }
+                    _ => {
+                        self.expression(condition);
+                        self.emit(Op::Assert(text));
+                    }
+                }
+            }
+            // Only the test runner runs trials, from the syntax tree.
+            StmtKind::Trial { .. } => {}
+            StmtKind::ExprStmt(expr) => {
+                self.expression(expr);
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(n) => {
+                self.chunk.numbers.push(*n);
+                let idx = (self.chunk.numbers.len() - 1) as u32;
+                self.emit(Op::Number(idx));
+            }
+            Expr::String(s) => {
+                let idx = self.constant(Value::String(s.clone()));
+                self.emit(Op::Constant(idx));
+            }
+            Expr::Boolean(b) => {
+                let idx = self.constant(Value::Boolean(*b));
+                self.emit(Op::Constant(idx));
+            }
+            Expr::Identifier(name, slot) => {
+                let name = self.name(name);
+                match slot {
+                    Slot::Local(idx) => self.emit(Op::LoadLocal(*idx, name)),
+                    Slot::Dynamic => self.emit(Op::Load(name)),
+                };
+            }
+            Expr::BinaryOp { left, op, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Op::Binary(*op));
+            }
+            Expr::Call { name, args } => {
+                let name = self.name(name);
+                self.emit(Op::Callee(name, args.len() as u32));
+                for arg in args {
+                    self.expression(arg);
+                }
+                self.emit(Op::Call(args.len() as u32));
+            }
+            Expr::Rewrite { target } => {
+                self.expression(target);
+                self.emit(Op::Mutate);
+            }
+            Expr::Superpose(exprs) => {
+                for e in exprs {
+                    self.expression(e);
+                }
+                self.emit(Op::Superpose(exprs.len() as u32));
+            }
+            Expr::Collapse(expr) => {
+                self.expression(expr);
+                self.emit(Op::Collapse);
+            }
+            Expr::Manifest(name) => {
+                let name = self.name(name);
+                self.emit(Op::Manifest(name));
+            }
+            Expr::Peek(name) => {
+                let name = self.name(name);
+                self.emit(Op::Peek(name));
+            }
+            Expr::Oldest(name) => {
+                let name = self.name(name);
+                self.emit(Op::Oldest(name));
+            }
+            Expr::Unearth(name) => {
+                let name = self.name(name);
+                self.emit(Op::Unearth(name));
+            }
+            Expr::Fragments(name) => {
+                let name = self.name(name);
+                self.emit(Op::Fragments(name));
+            }
+            Expr::Entangle(var1, var2) => {
+                let var1 = self.name(var1);
+                let var2 = self.name(var2);
+                self.emit(Op::Entangle(var1, var2));
+            }
+            Expr::Rift(expr) => {
+                self.emit(Op::Rift);
+                self.expression(expr);
+            }
+            Expr::Before(name, steps) => {
+                self.expression(steps);
+                let name = self.name(name);
+                self.emit(Op::Before(name));
+            }
+            Expr::During(name, timestamp) => {
+                self.expression(timestamp);
+                let name = self.name(name);
+                self.emit(Op::During(name));
+            }
+            Expr::Echoes(name) => {
+                let name = self.name(name);
+                self.emit(Op::Echoes(name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lexer, Parser, StandardPolicy};
+
+    fn compiled(source: &str) -> Chunk {
+        compile(&Parser::with_policy(Lexer::new(source).tokenize(), &StandardPolicy).parse_program().unwrap())
+    }
+
+    #[test]
+    fn whisper_and_assert_text_are_constants_not_names() {
+        let chunk = compiled("whisper \"mask w -> 1;\";\nassert w == 1;\nassert yellow;\n");
+        assert_eq!(chunk.names, ["w"]);
+        let texts: Vec<_> = chunk
+            .code
+            .iter()
+            .filter_map(|op| match op {
+                Op::Whisper(idx) | Op::Assert(idx) | Op::AssertBinary(_, idx) => Some(chunk.text(*idx)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, ["mask w -> 1;", "w == 1", "yellow"]);
+    }
+}
@@ -0,0 +1,336 @@
+// ============================================================================
+// DEBUGGER
+// ============================================================================
+//
+// Line breakpoints, stepping and watch expressions for sliced runs. The
+// machine asks `should_pause` before every statement; everything else here
+// reads a paused run without disturbing it. The tree-walker cannot pause, so
+// code run by a whisper executes as a single step.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::events::Events;
+use crate::json::{self, Object};
+use crate::vm::Machine;
+use crate::{inspect, Expr, Infection, Interpreter, Lexer, Parser, Scope, StandardPolicy, TemporalEcho, Token, Value};
+
+/// Statements a watch expression may run, so a runaway act cannot hang the debugger.
+const WATCH_FUEL: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum StepMode {
+    /// Run until a breakpoint.
+    #[default]
+    Continue,
+    /// Stop at the next statement, wherever it is.
+    Into,
+    /// Stop at the next statement no deeper than this many frames.
+    Over(usize),
+    /// Stop at the next statement shallower than this many frames.
+    Out(usize),
+}
+
+/// Where a run is paused: the statement's line and how many frames deep it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Location {
+    line: u32,
+    depth: usize,
+}
+
+impl Location {
+    pub(crate) fn to_json(self) -> String {
+        Object::new().raw("line", &self.line.to_string()).raw("depth", &self.depth.to_string()).finish()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Debugger {
+    breakpoints: BTreeSet<u32>,
+    watches: Vec<String>,
+    mode: StepMode,
+    /// Line of the statement reached last. A breakpoint fires when its line is entered,
+    /// not again for every statement or loop iteration on the same line.
+    last_line: Option<u32>,
+    /// Set by `resume` so the statement the run is paused on gets to execute.
+    resuming: bool,
+    paused: Option<Location>,
+}
+
+impl Debugger {
+    /// Forgets the previous run; breakpoints and watches stay.
+    pub(crate) fn reset(&mut self, stop_at_first: bool) {
+        self.mode = if stop_at_first { StepMode::Into } else { StepMode::Continue };
+        self.last_line = None;
+        self.resuming = false;
+        self.paused = None;
+    }
+
+    pub(crate) fn should_pause(&mut self, line: u32, depth: usize) -> bool {
+        if std::mem::take(&mut self.resuming) {
+            self.last_line = Some(line);
+            return false;
+        }
+        let entered = self.last_line != Some(line);
+        self.last_line = Some(line);
+        let stepped = match self.mode {
+            StepMode::Continue => false,
+            StepMode::Into => true,
+            StepMode::Over(frames) => depth <= frames,
+            StepMode::Out(frames) => depth < frames,
+        };
+        if stepped || (entered && self.breakpoints.contains(&line)) {
+            self.paused = Some(Location { line, depth });
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn paused(&self) -> Option<Location> {
+        self.paused
+    }
+
+    /// Lets a paused run continue: "continue", "into", "over" or "out".
+    pub(crate) fn resume(&mut self, mode: &str) -> Result<(), String> {
+        let Some(at) = self.paused else {
+            return Err("The run is not paused".to_string());
+        };
+        self.mode = match mode {
+            "continue" => StepMode::Continue,
+            "into" => StepMode::Into,
+            "over" => StepMode::Over(at.depth),
+            "out" => StepMode::Out(at.depth),
+            _ => return Err(format!("Unknown step mode '{}'", mode)),
+        };
+        self.paused = None;
+        self.resuming = true;
+        Ok(())
+    }
+
+    /// Stops a running program at its next statement.
+    pub(crate) fn interrupt(&mut self) {
+        self.mode = StepMode::Into;
+    }
+
+    pub(crate) fn set_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub(crate) fn clear_breakpoint(&mut self, line: u32) {
+        self.breakpoints.remove(&line);
+    }
+
+    pub(crate) fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub(crate) fn breakpoints_json(&self) -> String {
+        json::array(self.breakpoints.iter().map(|line| line.to_string()))
+    }
+
+    /// Adds a watch once it parses. Returns whether it was new.
+    pub(crate) fn add_watch(&mut self, source: &str) -> Result<bool, String> {
+        parse_watch(source)?;
+        if self.watches.iter().any(|watch| watch == source) {
+            return Ok(false);
+        }
+        self.watches.push(source.to_string());
+        Ok(true)
+    }
+
+    pub(crate) fn remove_watch(&mut self, source: &str) {
+        self.watches.retain(|watch| watch != source);
+    }
+
+    /// `[{"expression": ..., "value": ...} or {"expression": ..., "error": ...}]`, in the order added.
+    pub(crate) fn watches_json(&self, interp: &mut Interpreter) -> String {
+        json::array(self.watches.iter().map(|source| {
+            let object = Object::new().string("expression", source);
+            match interp.evaluate_watch(source) {
+                Ok(val) => object.raw("value", &inspect::value(&val)),
+                Err(e) => object.string("error", &e),
+            }
+            .finish()
+        }))
+    }
+}
+
+fn parse_watch(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser::with_policy(Lexer::new(source).tokenize(), &StandardPolicy);
+    let expr = parser.parse_expression()?;
+    if parser.current() != &Token::Eof {
+        return Err("Syntax Error".to_string());
+    }
+    Ok(expr)
+}
+
+/// `[{"act": null or name, "line": n, "variables": {...}}]`, outermost first. A frame's
+/// variables are its innermost scope, or the globals for the program before any scene.
+pub(crate) fn call_stack_json(machine: &Machine, interp: &Interpreter) -> String {
+    json::array(machine.backtrace(interp).into_iter().map(|frame| {
+        let act = frame.act.as_deref().map_or_else(|| "null".to_string(), json::quote);
+        let variables = match interp.call_stack[frame.scopes].last() {
+            Some(scope) => inspect::frame(scope),
+            None => inspect::environment(interp.own_globals()),
+        };
+        Object::new()
+            .raw("act", &act)
+            .raw("line", &frame.line.to_string())
+            .raw("variables", &variables)
+            .finish()
+    }))
+}
+
+/// Everything evaluating an expression can touch, put back once a watch has been read.
+struct Saved {
+    global_env: BTreeMap<String, Value>,
+    call_stack: Vec<Scope>,
+    sanity: f64,
+    sanity_seen: f64,
+    entropy: u64,
+    execution_depth: usize,
+    call_depth: usize,
+    fuel_used: u64,
+    out_of_fuel: bool,
+    fuel: Option<u64>,
+    memory_fragments: BTreeMap<String, VecDeque<Value>>,
+    temporal_echoes: VecDeque<TemporalEcho>,
+    infections: BTreeMap<String, Infection>,
+    reality_stable: bool,
+    phantom_variables: BTreeMap<String, Value>,
+    generated_code: Vec<String>,
+    whisper_count: usize,
+    rng: u64,
+}
+
+impl Interpreter {
+    fn save(&self) -> Saved {
+        Saved {
+            global_env: self.global_env.clone(),
+            call_stack: self.call_stack.clone(),
+            sanity: self.sanity,
+            sanity_seen: self.sanity_seen,
+            entropy: self.entropy,
+            execution_depth: self.execution_depth,
+            call_depth: self.call_depth,
+            fuel_used: self.fuel_used,
+            out_of_fuel: self.out_of_fuel,
+            fuel: self.limits.fuel,
+            memory_fragments: self.memory_fragments.clone(),
+            temporal_echoes: self.temporal_echoes.clone(),
+            infections: self.infections.clone(),
+            reality_stable: self.reality_stable,
+            phantom_variables: self.phantom_variables.clone(),
+            generated_code: self.generated_code.clone(),
+            whisper_count: self.whisper_count,
+            rng: self.rng.state(),
+        }
+    }
+
+    fn load(&mut self, saved: Saved) {
+        self.global_env = saved.global_env;
+        self.call_stack = saved.call_stack;
+        self.sanity = saved.sanity;
+        self.sanity_seen = saved.sanity_seen;
+        self.entropy = saved.entropy;
+        self.execution_depth = saved.execution_depth;
+        self.call_depth = saved.call_depth;
+        self.fuel_used = saved.fuel_used;
+        self.out_of_fuel = saved.out_of_fuel;
+        self.limits.fuel = saved.fuel;
+        self.memory_fragments = saved.memory_fragments;
+        self.temporal_echoes = saved.temporal_echoes;
+        self.infections = saved.infections;
+        self.reality_stable = saved.reality_stable;
+        self.phantom_variables = saved.phantom_variables;
+        self.generated_code = saved.generated_code;
+        self.whisper_count = saved.whisper_count;
+        self.rng.restore(saved.rng);
+    }
+
+    /// Evaluates `source` in the innermost frame as the program would, then undoes every
+    /// effect: sanity spent, echoes recorded, random draws taken.
+    pub(crate) fn evaluate_watch(&mut self, source: &str) -> Result<Value, String> {
+        let expr = parse_watch(source)?;
+        let saved = self.save();
+        // Draws a watch takes are not part of the run being recorded.
+        let recorder = self.recorder.take();
+        self.limits.fuel = Some(self.fuel_used.saturating_add(WATCH_FUEL));
+        let result = self.eval_expr(&expr, &mut Events::default());
+        self.recorder = recorder;
+        self.load(saved);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::YellowWebInterpreter;
+
+    const PROGRAM: &str = "\
+act double(n) {
+    mask d -> n * 2;
+    Carcosa d;
+}
+mask a -> double(2);
+mask b -> a + 1;
+echo(b);
+";
+
+    fn resume(interpreter: &mut YellowWebInterpreter, mode: &str) -> String {
+        interpreter.resume(mode).unwrap();
+        assert_eq!(interpreter.step(1000), "paused");
+        interpreter.get_location()
+    }
+
+    #[test]
+    fn steps_over_into_and_out_of_acts() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.debug(PROGRAM);
+        assert_eq!(interpreter.step(1000), "paused");
+        assert_eq!(interpreter.get_location(), r#"{"line":1,"depth":1}"#);
+        assert_eq!(resume(&mut interpreter, "over"), r#"{"line":5,"depth":1}"#);
+        assert_eq!(resume(&mut interpreter, "into"), r#"{"line":2,"depth":2}"#);
+        assert_eq!(resume(&mut interpreter, "out"), r#"{"line":6,"depth":1}"#);
+        interpreter.resume("continue").unwrap();
+        assert_eq!(interpreter.step(1000), "finished");
+        assert_eq!(interpreter.get_location(), "null");
+    }
+
+    #[test]
+    fn watches_follow_the_paused_frame() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.debug(PROGRAM);
+        interpreter.step(1000);
+        resume(&mut interpreter, "over");
+        resume(&mut interpreter, "into");
+        assert!(interpreter.add_watch("n * 10").unwrap());
+        assert!(interpreter.add_watch("a").unwrap());
+        assert!(!interpreter.add_watch("a").unwrap());
+        assert_eq!(
+            interpreter.get_watches(),
+            r#"[{"expression":"n * 10","value":20},{"expression":"a","error":"Undefined: 'a'"}]"#
+        );
+        resume(&mut interpreter, "out");
+        interpreter.remove_watch("n * 10");
+        assert_eq!(interpreter.get_watches(), r#"[{"expression":"a","value":4}]"#);
+    }
+
+    #[test]
+    fn breakpoints_pause_and_watches_cost_nothing() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.set_breakpoint(6);
+        interpreter.set_breakpoint(7);
+        interpreter.start(PROGRAM);
+        assert_eq!(interpreter.step(1000), "paused");
+        assert_eq!(interpreter.get_location(), r#"{"line":6,"depth":1}"#);
+        let sanity = interpreter.get_sanity();
+        interpreter.add_watch("superpose(a, 1)").unwrap();
+        interpreter.get_watches();
+        assert_eq!(interpreter.get_sanity(), sanity);
+        assert_eq!(resume(&mut interpreter, "continue"), r#"{"line":7,"depth":1}"#);
+        interpreter.clear_breakpoints();
+        assert_eq!(interpreter.get_breakpoints(), "[]");
+        interpreter.resume("continue").unwrap();
+        assert_eq!(interpreter.step(1000), "finished");
+    }
+}
@@ -0,0 +1,202 @@
+// ============================================================================
+// EVENTS
+// ============================================================================
+//
+// Everything a run reports, as typed events rather than lines of text. The
+// frontend reacts to `PhantomSpawned` or `InfectionSpread` directly, and
+// `render_text` turns the stream back into the classic transcript.
+
+use crate::json::{self, Object};
+use crate::SanityThreshold;
+
+/// Events a single run may record before the rest are dropped.
+const MAX_EVENTS: usize = 10_000;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Phase {
+    Parse,
+    Runtime,
+    /// The run spent the fuel it was given.
+    Fuel,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Event {
+    /// A line printed by `echo`, as distorted as sanity made it.
+    Echo { text: String, distorted: bool },
+    Warning { text: String },
+    /// Neutral commentary: banners, whispers taking shape, reality stabilizing.
+    Narration { text: String },
+    PhantomSpawned { name: String },
+    /// `source` is `None` when the variable was infected directly.
+    InfectionSpread { source: Option<String>, target: String },
+    Collapse { value: String },
+    /// Sanity fell below `threshold`. Silent in the transcript.
+    SanityThreshold { threshold: SanityThreshold, sanity: f64 },
+    Error { phase: Phase, message: String },
+    /// The abyss muttering on the current line instead of starting a new one.
+    Murmur { text: String },
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::Echo { .. } => "echo",
+            Event::Warning { .. } => "warning",
+            Event::Narration { .. } => "narration",
+            Event::PhantomSpawned { .. } => "phantom_spawned",
+            Event::InfectionSpread { .. } => "infection_spread",
+            Event::Collapse { .. } => "collapse",
+            Event::SanityThreshold { .. } => "sanity_threshold",
+            Event::Error { .. } => "error",
+            Event::Murmur { .. } => "murmur",
+        }
+    }
+
+    /// The event as it appears in the transcript, without its line break.
+    pub(crate) fn text(&self) -> String {
+        match self {
+            Event::Echo { text, .. } | Event::Warning { text } | Event::Narration { text } | Event::Murmur { text } => {
+                text.clone()
+            }
+            Event::PhantomSpawned { name } => format!("⚠ Phantom variable '{}' manifests from the void...", name),
+            Event::InfectionSpread { source: None, target } => {
+                format!("⚠ Variable '{}' infected. Contagion spreads...", target)
+            }
+            Event::InfectionSpread { source: Some(_), target } => format!("  ↳ Infection spreads to '{}'", target),
+            Event::Collapse { value } => format!("|ψ⟩ Collapsed to: {}", value),
+            Event::SanityThreshold { .. } => String::new(),
+            Event::Error { phase: Phase::Parse, message } => format!("\n⚠ Parse error: {}", message),
+            Event::Error { phase: Phase::Runtime, message } => format!("\n⚠ Runtime horror: {}", message),
+            Event::Error { phase: Phase::Fuel, message } => format!("\n⚠ {}", message),
+        }
+    }
+
+    /// The ANSI escape a terminal colours the event with, if any.
+    fn colour(&self) -> Option<&'static str> {
+        match self {
+            Event::Echo { distorted: true, .. } => Some(MAGENTA),
+            Event::Warning { .. } | Event::PhantomSpawned { .. } | Event::InfectionSpread { .. } => Some(YELLOW),
+            Event::Collapse { .. } => Some(CYAN),
+            Event::Error { .. } => Some(RED),
+            Event::Murmur { .. } => Some(DIM),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let object = Object::new().string("kind", self.kind());
+        let object = match self {
+            Event::PhantomSpawned { name } => object.string("name", name),
+            Event::InfectionSpread { source, target } => {
+                let source = source.as_deref().map_or_else(|| "null".to_string(), json::quote);
+                object.raw("source", &source).string("target", target)
+            }
+            Event::Collapse { value } => object.string("value", value),
+            Event::SanityThreshold { threshold, sanity } => {
+                object.string("threshold", threshold.name()).number("sanity", *sanity)
+            }
+            Event::Error { phase, message } => {
+                let phase = match phase {
+                    Phase::Parse => "parse",
+                    Phase::Runtime => "runtime",
+                    Phase::Fuel => "fuel",
+                };
+                object.string("phase", phase).string("message", message)
+            }
+            _ => object,
+        };
+        object.string("text", &self.text()).finish()
+    }
+}
+
+/// The event stream of one run.
+#[derive(Debug, Default)]
+pub(crate) struct Events {
+    events: Vec<Event>,
+    /// Events refused once the run hit `MAX_EVENTS`.
+    dropped: usize,
+    /// Set once the program has stopped; the closing summary is never refused.
+    finished: bool,
+    /// How many events `take_json` has already handed out.
+    taken: usize,
+}
+
+impl Events {
+    /// Records an event, unless the running program has already said too much.
+    pub(crate) fn emit(&mut self, event: Event) {
+        if self.finished || self.events.len() < MAX_EVENTS {
+            self.events.push(event);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Marks the end of the program's own output, owning up to anything that was dropped.
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+        if self.dropped > 0 {
+            self.warn(format!("⚠ {} more events were lost to the void.", self.dropped));
+        }
+    }
+
+    pub(crate) fn warn(&mut self, text: impl Into<String>) {
+        self.emit(Event::Warning { text: text.into() });
+    }
+
+    pub(crate) fn narrate(&mut self, text: impl Into<String>) {
+        self.emit(Event::Narration { text: text.into() });
+    }
+
+    pub(crate) fn render_text(&self) -> String {
+        self.render(false)
+    }
+
+    /// The transcript coloured for a terminal: warnings yellow, horrors red, distorted echoes magenta.
+    pub(crate) fn render_ansi(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, ansi: bool) -> String {
+        let mut out = String::new();
+        for event in &self.events {
+            let text = match event {
+                Event::SanityThreshold { .. } => continue,
+                Event::Murmur { text } => text.clone(),
+                event => event.text() + "\n",
+            };
+            match event.colour() {
+                // Colour the line itself, leaving any blank line before it and the break after it plain.
+                Some(colour) if ansi => {
+                    let body = text.trim_matches('\n');
+                    let start = text.find(body).unwrap_or(0);
+                    out.push_str(&text[..start]);
+                    out.push_str(colour);
+                    out.push_str(body);
+                    out.push_str(RESET);
+                    out.push_str(&text[start + body.len()..]);
+                }
+                _ => out.push_str(&text),
+            }
+        }
+        out
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        json::array(self.events.iter().map(Event::to_json))
+    }
+
+    /// The events recorded since the last call, as JSON.
+    pub(crate) fn take_json(&mut self) -> String {
+        let fresh = json::array(self.events[self.taken..].iter().map(Event::to_json));
+        self.taken = self.events.len();
+        fresh
+    }
+}
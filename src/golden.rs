@@ -0,0 +1,227 @@
+// ============================================================================
+// GOLDEN FILES
+// ============================================================================
+//
+// Regression tests as plain Yellow programs. Each `name.yellow` keeps its
+// expected output in `name.out` and its expected final sanity in
+// `name.sanity` beside it. A run uses a fixed seed, so the same program
+// always drains the same sanity and suffers the same horrors; both engines
+// must agree with the expectation. Programs in a directory named `lucid` run
+// in lucid mode. Blessing writes down whatever the program does now. Any
+// `trial` blocks in the program are run as well, and must pass. Goldens live
+// on a filesystem, so the WebAssembly build leaves this module out.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{run_trials, Engine, TrialOutcome, YellowWebInterpreter, DEFAULT_SEED};
+
+/// Lines of unchanged output shown around each difference.
+const CONTEXT: usize = 2;
+
+/// How close final sanity must come to its expectation.
+const SANITY_TOLERANCE: f64 = 1e-9;
+
+/// One golden program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Golden {
+    pub source: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Passed,
+    /// The expectations were written from this run.
+    Blessed,
+    /// What went wrong, with a diff of the output if it differed.
+    Failed(String),
+}
+
+/// What a program did when run.
+struct Outcome {
+    output: String,
+    sanity: f64,
+}
+
+fn outcome(source: &str, engine: &str, lucid: bool) -> Result<Outcome, String> {
+    let mut interpreter = YellowWebInterpreter::with_seed(DEFAULT_SEED);
+    interpreter.set_engine(engine)?;
+    interpreter.set_lucid(lucid);
+    interpreter.run_code(source);
+    Ok(Outcome { output: interpreter.get_output(), sanity: interpreter.get_sanity() })
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Collects the `.yellow` files under `dir`, skipping hidden directories and `target`.
+fn walk(dir: &Path, found: &mut Vec<Golden>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                walk(&path, found)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "yellow") {
+            found.push(Golden { source: path });
+        }
+    }
+    Ok(())
+}
+
+impl Golden {
+    /// Every golden program among `paths`: files are taken as they are, directories
+    /// are searched recursively. Sorted, so runs list them in a stable order.
+    pub fn discover(paths: &[PathBuf]) -> Result<Vec<Golden>, String> {
+        let mut found = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                walk(path, &mut found)?;
+            } else if path.exists() {
+                found.push(Golden { source: path.clone() });
+            } else {
+                return Err(format!("{} does not exist", path.display()));
+            }
+        }
+        found.sort_by(|a, b| a.source.cmp(&b.source));
+        found.dedup();
+        Ok(found)
+    }
+
+    /// Whether the program sits in a `lucid` directory.
+    pub fn lucid(&self) -> bool {
+        self.source.parent().and_then(Path::file_name).is_some_and(|dir| dir == "lucid")
+    }
+
+    pub fn expected_output(&self) -> PathBuf {
+        self.source.with_extension("out")
+    }
+
+    pub fn expected_sanity(&self) -> PathBuf {
+        self.source.with_extension("sanity")
+    }
+
+    /// Runs the program and compares it with its expectations, or, when `bless` is
+    /// set, writes them. `Err` only when the files themselves cannot be used.
+    pub fn run(&self, bless: bool) -> Result<Verdict, String> {
+        let source = read(&self.source)?;
+        let actual = outcome(&source, "bytecode", self.lucid())?;
+        let tree = outcome(&source, "tree", self.lucid())?;
+        if tree.output != actual.output {
+            return Ok(Verdict::Failed(format!(
+                "the engines disagree (- bytecode, + tree-walker):\n{}",
+                diff(&actual.output, &tree.output)
+            )));
+        }
+        if tree.sanity.to_bits() != actual.sanity.to_bits() {
+            return Ok(Verdict::Failed(format!(
+                "the engines disagree: the bytecode VM ends at {}% sanity, the tree-walker at {}%",
+                actual.sanity, tree.sanity
+            )));
+        }
+
+        if bless {
+            write(&self.expected_output(), &actual.output)?;
+            write(&self.expected_sanity(), &format!("{}\n", actual.sanity))?;
+            return Ok(Verdict::Blessed);
+        }
+        if !self.expected_output().exists() || !self.expected_sanity().exists() {
+            return Ok(Verdict::Failed("no expectations yet; bless it to record them".to_string()));
+        }
+
+        let mut problems = String::new();
+        let expected = read(&self.expected_output())?;
+        if expected != actual.output {
+            let _ = writeln!(problems, "output differs (- expected, + actual):\n{}", diff(&expected, &actual.output));
+        }
+        let text = read(&self.expected_sanity())?;
+        let sanity: f64 = text
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} does not hold a number", self.expected_sanity().display()))?;
+        if (sanity - actual.sanity).abs() > SANITY_TOLERANCE {
+            let _ = writeln!(problems, "sanity ends at {}%, expected {}%", actual.sanity, sanity);
+        }
+        Ok(if problems.is_empty() { Verdict::Passed } else { Verdict::Failed(problems) })
+    }
+
+    /// Runs the program's trials on both engines. A trial the engines disagree about fails.
+    pub fn trials(&self) -> Result<Vec<TrialOutcome>, String> {
+        let source = read(&self.source)?;
+        let bytecode = run_trials(&source, DEFAULT_SEED, Engine::Bytecode)?;
+        let tree = run_trials(&source, DEFAULT_SEED, Engine::TreeWalk)?;
+        Ok(bytecode
+            .into_iter()
+            .zip(tree)
+            .map(|(mut outcome, tree)| {
+                if outcome != tree && outcome.passed() {
+                    outcome.failure = Some(format!(
+                        "the engines disagree: the tree-walker {}",
+                        tree.failure.map_or("passes it differently".to_string(), |e| format!("fails with: {}", e))
+                    ));
+                }
+                outcome
+            })
+            .collect())
+    }
+}
+
+/// A line diff of `expected` against `actual`: removed lines start with `-`, added
+/// ones with `+`, and unchanged ones near a change with a space.
+fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    // lengths[i][j]: the longest common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len()).filter(|&k| lines[k].0 != ' ').collect();
+    let near = |k: usize| changed.iter().any(|&c| c.abs_diff(k) <= CONTEXT);
+    let mut out = String::new();
+    let mut skipped = false;
+    for (k, (mark, line)) in lines.iter().enumerate() {
+        if near(k) {
+            let _ = writeln!(out, "{} {}", mark, line);
+            skipped = false;
+        } else if !skipped {
+            out.push_str("  ...\n");
+            skipped = true;
+        }
+    }
+    // Output that differs only in its final newline has no changed lines to show.
+    if changed.is_empty() {
+        out = "  (the outputs differ only in trailing newlines)\n".to_string();
+    }
+    out
+}
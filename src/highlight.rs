@@ -0,0 +1,124 @@
+// ============================================================================
+// HIGHLIGHTING
+// ============================================================================
+//
+// Splits source into classified spans for syntax highlighting, comments
+// included. It runs the lexer alone, never the parser, so any text at all,
+// however unfinished, gets an answer: an editor can call it on every
+// keystroke.
+
+use crate::json::{self, Object};
+use crate::{Lexer, Token};
+
+fn kind(token: &Token) -> &'static str {
+    match token {
+        Token::Hastur | Token::Cassilda | Token::Carcosa => "horror",
+        Token::Superpose | Token::Collapse | Token::Entangle => "quantum",
+        Token::Identifier(_) => "identifier",
+        Token::Number(_) => "number",
+        Token::String(_) => "string",
+        Token::Pallid | Token::Yellow | Token::Tattered => "constant",
+        Token::Becomes
+        | Token::Whispers
+        | Token::Screams
+        | Token::Ascending
+        | Token::Descending
+        | Token::Merged
+        | Token::Torn
+        | Token::Reflected
+        | Token::Shattered => "operator",
+        Token::LParen | Token::RParen | Token::LBrace | Token::RBrace | Token::Comma | Token::Semicolon => "punctuation",
+        _ => "keyword",
+    }
+}
+
+/// `[{"kind", "line", "column", "start", "end"}]` in source order. `line` and `column`
+/// count characters from 1, as error messages do; `start` and `end` are UTF-16 offsets,
+/// so JavaScript can `slice` the source with them. Characters that start no token come
+/// out as "unknown".
+pub(crate) fn classify(source: &str) -> String {
+    let mut lexer = Lexer::new(source);
+    // UTF-16 offset of each character, and of the end.
+    let mut offsets = Vec::with_capacity(lexer.input.len() + 1);
+    let mut offset = 0;
+    for c in &lexer.input {
+        offsets.push(offset);
+        offset += c.len_utf16();
+    }
+    offsets.push(offset);
+
+    let mut spans = Vec::new();
+    loop {
+        lexer.skip_whitespace();
+        let span = lexer.span();
+        let start = lexer.position;
+        let kind = match lexer.current_char {
+            None => break,
+            Some('#') => {
+                lexer.read_comment();
+                "comment"
+            }
+            Some(_) => lexer.scan().as_ref().map_or("unknown", kind),
+        };
+        spans.push(
+            Object::new()
+                .string("kind", kind)
+                .raw("line", &span.line.to_string())
+                .raw("column", &span.column.to_string())
+                .raw("start", &offsets[start].to_string())
+                .raw("end", &offsets[lexer.position].to_string())
+                .finish(),
+        );
+    }
+    json::array(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Json;
+
+    /// `(kind, column, start, end)` for every span.
+    fn spans(source: &str) -> Vec<(String, u64, u64, u64)> {
+        let spans = json::parse(&classify(source)).unwrap();
+        let field = |span: &Json, key: &str| span.get(key).and_then(Json::as_u64).unwrap();
+        spans
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|span| {
+                let kind = span.get("kind").and_then(Json::as_str).unwrap().to_string();
+                (kind, field(span, "column"), field(span, "start"), field(span, "end"))
+            })
+            .collect()
+    }
+
+    fn kinds(source: &str) -> Vec<String> {
+        spans(source).into_iter().map(|(kind, ..)| kind).collect()
+    }
+
+    #[test]
+    fn classifies_every_token_and_comment() {
+        assert_eq!(
+            kinds("mask a -> 1; # hi\nHastur (yellow) { echo(superpose(a)); }"),
+            [
+                "keyword", "identifier", "operator", "number", "punctuation", "comment", "horror", "punctuation",
+                "constant", "punctuation", "punctuation", "keyword", "punctuation", "quantum", "punctuation",
+                "identifier", "punctuation", "punctuation", "punctuation", "punctuation",
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets_are_utf16_and_columns_are_characters() {
+        let found = spans("echo(\"𝔜\");");
+        assert_eq!(found[2], ("string".to_string(), 6, 5, 9));
+        assert_eq!(found[3], ("punctuation".to_string(), 9, 9, 10));
+    }
+
+    #[test]
+    fn unfinished_text_still_gets_spans() {
+        assert_eq!(kinds("echo(@ \"open"), ["keyword", "punctuation", "unknown", "string"]);
+        assert!(kinds("").is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+// ============================================================================
+// HOST ACTS
+// ============================================================================
+//
+// Acts the embedding program supplies, so Yellow code can reach outside the
+// interpreter: a Rust closure for native hosts, or a JavaScript function for
+// the web app. Once registered they live in the globals beside the built-in
+// acts and are called the same way by both engines. A host act sees only its
+// arguments; whatever it does to the world outside is its own business.
+
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::builtins::Native;
+use crate::{Interpreter, Lexer, Token, Value};
+
+pub(crate) type HostAct = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+// Just enough of `Function` and `Array` to call back into JavaScript, without js-sys.
+#[wasm_bindgen]
+extern "C" {
+    /// A JavaScript function registered as an act.
+    #[wasm_bindgen(js_name = Function)]
+    pub type JsAct;
+
+    #[wasm_bindgen(method, catch)]
+    fn apply(this: &JsAct, context: &JsValue, args: &JsArgs) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = Array)]
+    type JsArgs;
+
+    #[wasm_bindgen(constructor, js_class = "Array")]
+    fn new() -> JsArgs;
+
+    #[wasm_bindgen(method, js_class = "Array")]
+    fn push(this: &JsArgs, value: &JsValue) -> u32;
+
+    /// Whatever a callback threw; an `Error` has a message.
+    type Thrown;
+
+    #[wasm_bindgen(method, getter)]
+    fn message(this: &Thrown) -> Option<String>;
+}
+
+impl Interpreter {
+    /// Installs `act` as the global `name`, replacing whatever was there.
+    pub(crate) fn register_act(&mut self, name: &str, arity: usize, act: HostAct) -> Result<(), String> {
+        let tokens = Lexer::new(name).tokenize();
+        if !matches!(tokens.first().map(|t| &t.token), Some(Token::Identifier(n)) if n == name) || tokens.len() != 2 {
+            return Err(format!("'{}' cannot name an act", name));
+        }
+        self.global_env.insert(name.to_string(), Value::Native(Rc::new(Native::host(name, arity, act))));
+        Ok(())
+    }
+}
+
+/// Numbers, strings and booleans cross as themselves and pallid as `null`; anything
+/// else arrives as the text `echo` would print.
+fn to_js(val: &Value) -> JsValue {
+    match val {
+        Value::Number(n) => JsValue::from_f64(*n),
+        Value::String(s) => JsValue::from_str(s),
+        Value::Boolean(b) => JsValue::from_bool(*b),
+        Value::Null => JsValue::NULL,
+        val => JsValue::from_str(&val.to_string()),
+    }
+}
+
+fn from_js(name: &str, val: JsValue) -> Result<Value, String> {
+    if let Some(n) = val.as_f64() {
+        Ok(Value::Number(n))
+    } else if let Some(s) = val.as_string() {
+        Ok(Value::String(s))
+    } else if let Some(b) = val.as_bool() {
+        Ok(Value::Boolean(b))
+    } else if val.is_null() || val.is_undefined() {
+        Ok(Value::Null)
+    } else {
+        Err(format!("{} answered with something Yellow cannot hold", name))
+    }
+}
+
+/// Wraps a JavaScript function as a host act. What it throws becomes a runtime horror.
+pub(crate) fn js_act(name: &str, act: JsAct) -> HostAct {
+    let name = name.to_string();
+    Rc::new(move |args: &[Value]| {
+        let list = JsArgs::new();
+        for arg in args {
+            list.push(&to_js(arg));
+        }
+        match act.apply(&JsValue::NULL, &list) {
+            Ok(val) => from_js(&name, val),
+            Err(thrown) => {
+                let message = thrown.unchecked_ref::<Thrown>().message().or_else(|| thrown.as_string());
+                Err(format!("{} failed: {}", name, message.unwrap_or_else(|| "an unknown error".to_string())))
+            }
+        }
+    })
+}
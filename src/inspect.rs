@@ -0,0 +1,186 @@
+// ============================================================================
+// INSPECTION
+// ============================================================================
+//
+// Read-only JSON views of interpreter state for the editor's memory panel.
+// Values that JSON can hold natively are written as-is; acts and quantum
+// states become objects tagged by `kind`. Terminals get the same state as
+// plain text from `report`.
+
+use std::fmt::Write;
+
+use crate::json::{self, Object};
+use crate::{Interpreter, QuantumState, Scope, Value};
+
+pub(crate) fn value(val: &Value) -> String {
+    match val {
+        Value::Number(n) => json::number(*n),
+        Value::String(s) => json::quote(s),
+        Value::Boolean(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        Value::Function { act, .. } => Object::new()
+            .string("kind", "act")
+            .raw("params", &json::array(act.params().iter().map(|p| json::quote(p))))
+            .finish(),
+        Value::Native(native) => Object::new()
+            .string("kind", "native")
+            .string("name", native.name())
+            .raw("arity", &native.arity().to_string())
+            .finish(),
+        Value::History(vals) => {
+            Object::new().string("kind", "history").raw("values", &json::array(vals.iter().map(value))).finish()
+        }
+        Value::Quantum(qs) => match &**qs {
+            QuantumState::Superposition(states) => Object::new()
+                .string("kind", "superposition")
+                .raw("states", &json::array(states.iter().map(value)))
+                .finish(),
+            QuantumState::Entangled(name) => Object::new().string("kind", "entangled").string("with", name).finish(),
+            QuantumState::Phantom => Object::new().string("kind", "phantom").finish(),
+        },
+    }
+}
+
+/// A value as `echo` would print it, except that acts show their parameters.
+fn text(val: &Value) -> String {
+    match val {
+        Value::Function { act, .. } => format!("<act({})>", act.params().join(", ")),
+        Value::Native(native) => format!("<{}/{}>", native.name(), native.arity()),
+        val => val.to_string(),
+    }
+}
+
+pub(crate) fn environment<'a, I: IntoIterator<Item = (&'a String, &'a Value)>>(vars: I) -> String {
+    vars.into_iter().fold(Object::new(), |object, (name, val)| object.raw(name, &value(val))).finish()
+}
+
+pub(crate) fn frame(scope: &Scope) -> String {
+    let names = scope.keys();
+    environment(names.iter().filter_map(|name| scope.get(name).map(|val| (name, val))))
+}
+
+impl Interpreter {
+    /// `{"globals": {...}, "frames": [{...}, ...]}`, innermost frame last. Built-in acts
+    /// are left out of the globals.
+    pub(crate) fn variables_json(&self) -> String {
+        Object::new()
+            .raw("globals", &environment(self.own_globals()))
+            .raw("frames", &json::array(self.call_stack.iter().map(frame)))
+            .finish()
+    }
+
+    pub(crate) fn infections_json(&self) -> String {
+        json::array(self.infections.iter().map(|(name, infection)| {
+            Object::new()
+                .string("name", name)
+                .string("source", &infection.source)
+                .number("virulence", infection.virulence)
+                .number("mutation_vector", infection.mutation_vector as f64)
+                .finish()
+        }))
+    }
+
+    /// Oldest first.
+    pub(crate) fn echoes_json(&self) -> String {
+        json::array(self.temporal_echoes.iter().map(|echo| {
+            Object::new()
+                .number("timestamp", echo.timestamp as f64)
+                .string("name", &echo.variable_name)
+                .raw("value", &value(&echo.ghost_value))
+                .number("stability", echo.stability)
+                .finish()
+        }))
+    }
+
+    /// Each name's fragments, oldest first.
+    pub(crate) fn fragments_json(&self) -> String {
+        self.memory_fragments
+            .iter()
+            .fold(Object::new(), |object, (name, fragments)| {
+                object.raw(name, &json::array(fragments.iter().map(value)))
+            })
+            .finish()
+    }
+
+    pub(crate) fn phantoms_json(&self) -> String {
+        environment(&self.phantom_variables)
+    }
+
+    pub(crate) fn generated_code_json(&self) -> String {
+        json::array(self.generated_code.iter().map(|code| json::quote(code)))
+    }
+
+    /// Everything above in one object, plus the scalar readings.
+    pub(crate) fn state_json(&self) -> String {
+        Object::new()
+            .number("sanity", self.sanity)
+            .number("entropy", self.entropy as f64)
+            .raw("lucid", &self.lucid.to_string())
+            .raw("reality_stable", &self.reality_stable.to_string())
+            .raw("variables", &self.variables_json())
+            .raw("infections", &self.infections_json())
+            .raw("echoes", &self.echoes_json())
+            .raw("fragments", &self.fragments_json())
+            .raw("phantoms", &self.phantoms_json())
+            .raw("generated_code", &self.generated_code_json())
+            .finish()
+    }
+
+    /// `topic` as plain text, one entry a line: "sanity", "variables", "infections",
+    /// "echoes", "fragments" or "phantoms".
+    pub(crate) fn report(&self, topic: &str) -> Result<String, String> {
+        let mut out = String::new();
+        match topic {
+            "sanity" => {
+                let _ = writeln!(out, "Sanity: {:.1}%", self.sanity.max(0.0));
+                let _ = writeln!(out, "Entropy: {}", self.entropy);
+                let _ = writeln!(out, "Reality: {}", if self.reality_stable { "stable" } else { "unanchored" });
+                if self.lucid {
+                    out.push_str("Lucid\n");
+                }
+            }
+            "variables" => {
+                for (name, val) in self.own_globals() {
+                    let _ = writeln!(out, "{} = {}", name, text(val));
+                }
+            }
+            "infections" => {
+                for (name, infection) in &self.infections {
+                    let _ = writeln!(
+                        out,
+                        "{} (from {}): virulence {:.2}, {} mutations",
+                        name, infection.source, infection.virulence, infection.mutation_vector
+                    );
+                }
+            }
+            "echoes" => {
+                for echo in &self.temporal_echoes {
+                    let _ = writeln!(
+                        out,
+                        "@{} {} = {} (stability {:.2})",
+                        echo.timestamp,
+                        echo.variable_name,
+                        text(&echo.ghost_value),
+                        echo.stability
+                    );
+                }
+            }
+            "fragments" => {
+                for (name, fragments) in &self.memory_fragments {
+                    let values: Vec<_> = fragments.iter().map(text).collect();
+                    let _ = writeln!(out, "{}: {}", name, values.join(", "));
+                }
+            }
+            "phantoms" => {
+                for (name, val) in &self.phantom_variables {
+                    let _ = writeln!(out, "{} = {}", name, text(val));
+                }
+            }
+            _ => return Err(format!("Nothing to report on '{}'", topic)),
+        }
+        if out.is_empty() {
+            out.push_str("(nothing)\n");
+        }
+        Ok(out)
+    }
+}
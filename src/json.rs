@@ -0,0 +1,305 @@
+// ============================================================================
+// JSON
+// ============================================================================
+//
+// Just enough JSON to hand structured data to JavaScript, and to read back
+// what we handed out, without pulling in a serialization framework.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub(crate) fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// JSON has no NaN or infinities; they become `null`.
+pub(crate) fn number(n: f64) -> String {
+    if n.is_finite() { format!("{}", n) } else { "null".to_string() }
+}
+
+/// Like `number`, but NaN and the infinities survive as the strings Rust parses them from.
+pub(crate) fn real(n: f64) -> String {
+    if n.is_finite() { number(n) } else { quote(&n.to_string()) }
+}
+
+pub(crate) fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Builds a JSON object one field at a time.
+pub(crate) struct Object {
+    out: String,
+}
+
+impl Object {
+    pub(crate) fn new() -> Self {
+        Object { out: String::from("{") }
+    }
+
+    /// Adds a field whose value is already JSON.
+    pub(crate) fn raw(mut self, key: &str, json: &str) -> Self {
+        if self.out.len() > 1 {
+            self.out.push(',');
+        }
+        self.out.push_str(&quote(key));
+        self.out.push(':');
+        self.out.push_str(json);
+        self
+    }
+
+    pub(crate) fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, &quote(value))
+    }
+
+    pub(crate) fn number(self, key: &str, value: f64) -> Self {
+        self.raw(key, &number(value))
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.out.push('}');
+        self.out
+    }
+}
+
+// ============================================================================
+// READING
+// ============================================================================
+
+/// A parsed JSON document. Numbers keep their text so integers wider than
+/// an `f64` survive the trip.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// A number written by `real`.
+    pub(crate) fn as_real(&self) -> Option<f64> {
+        match self {
+            Json::String(text) => text.parse().ok(),
+            json => json.as_f64(),
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object(&self) -> Option<&BTreeMap<String, Json>> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<Json, String> {
+    let mut reader = Reader { chars: text.chars().peekable() };
+    let json = reader.value()?;
+    reader.skip_whitespace();
+    match reader.chars.next() {
+        None => Ok(json),
+        Some(c) => Err(format!("Unexpected '{}' after the document", c)),
+    }
+}
+
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("Expected '{}' but found '{}'", expected, c)),
+            None => Err(format!("Expected '{}' but the document ended", expected)),
+        }
+    }
+
+    fn word(&mut self, word: &str, json: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("Expected '{}'", word));
+            }
+        }
+        Ok(json)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.word("true", Json::Bool(true)),
+            Some('f') => self.word("false", Json::Bool(false)),
+            Some('n') => self.word("null", Json::Null),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+            None => Err("The document ended early".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let mut text = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            text.push(c);
+        }
+        match text.parse::<f64>() {
+            Ok(_) => Ok(Json::Number(text)),
+            Err(_) => Err(format!("Malformed number '{}'", text)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => out.push(self.escaped_char()?),
+                    Some(c @ ('"' | '\\' | '/')) => out.push(c),
+                    _ => return Err("Malformed escape in string".to_string()),
+                },
+                Some(c) => out.push(c),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+
+    /// The rest of a `\u` escape, including the low half of a surrogate pair.
+    fn escaped_char(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err("Unpaired surrogate in string".to_string());
+            }
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "Invalid character escape in string".to_string())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.next().and_then(|c| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| "Malformed \\u escape in string".to_string())?;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err("Expected ',' or ']' in array".to_string()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = BTreeMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err("Expected ',' or '}' in object".to_string()),
+            }
+        }
+    }
+}
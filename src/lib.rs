@@ -1,12 +1,46 @@
 use wasm_bindgen::prelude::*;
+use std::cell::OnceCell;
 use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
+use std::rc::Rc;
+
+mod builtins;
+mod checker;
+mod compiler;
+mod debugger;
+mod events;
+#[cfg(not(target_arch = "wasm32"))]
+mod golden;
+mod highlight;
+mod host;
+mod inspect;
+mod json;
+#[cfg(not(target_arch = "wasm32"))]
+mod lsp;
+mod printer;
+mod resolver;
+mod snapshot;
+mod trace;
+mod trial;
+mod vm;
+
+pub use builtins::Native;
+pub use checker::{Diagnostic, Severity};
+#[cfg(not(target_arch = "wasm32"))]
+pub use golden::{Golden, Verdict};
+#[cfg(not(target_arch = "wasm32"))]
+pub use lsp::LanguageServer;
+pub use trial::{run_trials, TrialOutcome};
+
+use compiler::Chunk;
+use debugger::Debugger;
+use events::{Event, Events, Phase};
+use trace::{Effect, Recorder, Trace};
 
 // Removed panic hook to reduce allocation noise and potential dlmalloc conflict
 #[derive(Debug, Clone)]
-enum QuantumState {
-    Collapsed(Box<Value>),
-    Superposition(Vec<Box<Value>>),
+pub enum QuantumState {
+    Superposition(Vec<Value>),
     Entangled(String),
     Phantom,
 }
@@ -34,16 +68,373 @@ struct TemporalEcho {
     stability: f64,
 }
 
+/// How many temporal echoes survive once `set_var` records a new one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EchoRetention {
+    /// Every assignment is remembered forever.
+    Unbounded,
+    /// Keep the newest `n` echoes across all variables.
+    Total(usize),
+    /// Keep the newest `n` echoes of each variable.
+    PerVariable(usize),
+    /// Keep echoes recorded within the last `n` entropy ticks.
+    Window(u64),
+}
+
+impl Default for EchoRetention {
+    fn default() -> Self {
+        EchoRetention::Total(50)
+    }
+}
+
+impl EchoRetention {
+    fn parse(mode: &str, limit: u32) -> Result<Self, String> {
+        match mode {
+            "unbounded" => Ok(EchoRetention::Unbounded),
+            "total" => Ok(EchoRetention::Total(limit as usize)),
+            "per_variable" => Ok(EchoRetention::PerVariable(limit as usize)),
+            "window" => Ok(EchoRetention::Window(limit as u64)),
+            _ => Err(format!("Unknown echo retention '{}'", mode)),
+        }
+    }
+
+    /// The mode and limit `parse` would take to build this retention.
+    fn describe(self) -> (&'static str, u64) {
+        match self {
+            EchoRetention::Unbounded => ("unbounded", 0),
+            EchoRetention::Total(n) => ("total", n as u64),
+            EchoRetention::PerVariable(n) => ("per_variable", n as u64),
+            EchoRetention::Window(n) => ("window", n),
+        }
+    }
+}
+
+// ============================================================================ 
+// RNG
+// ============================================================================ 
+
+const DEFAULT_SEED: u64 = 123456789;
+
+/// Source of every random decision the interpreter makes.
+pub trait Rng {
+    /// Next draw in `[0, 1)`.
+    fn next_f64(&mut self) -> f64;
+    /// Where the generator is in its sequence, as only this kind of generator understands it:
+    /// the seed of the next draw for `XorShift`, the number of draws served for `ScriptedRng`.
+    fn state(&self) -> u64;
+    /// Rewinds or fast-forwards to a state previously returned by `state`.
+    fn restore(&mut self, state: u64);
+}
+
+/// The default Xorshift generator. Pure Rust, so no JS re-entrancy during execution.
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift never leaves the all-zero state, so 0 starts from its splitmix64 scramble,
+        // which no other seed starts from.
+        XorShift { state: if seed == 0 { splitmix64(0) } else { seed } }
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Rng for XorShift {
+    fn next_f64(&mut self) -> f64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        (x % 10000) as f64 / 10000.0
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn restore(&mut self, state: u64) {
+        *self = XorShift::new(state);
+    }
+}
+
+/// Replays a fixed sequence of draws, cycling when it runs out. Its state is a position
+/// in the sequence, not a seed.
+pub struct ScriptedRng {
+    draws: Vec<f64>,
+    position: usize,
+}
+
+impl ScriptedRng {
+    pub fn new(draws: Vec<f64>) -> Self {
+        ScriptedRng { draws, position: 0 }
+    }
+}
+
+impl Rng for ScriptedRng {
+    fn next_f64(&mut self) -> f64 {
+        if self.draws.is_empty() {
+            return 0.0;
+        }
+        let draw = self.draws[self.position % self.draws.len()];
+        self.position += 1;
+        draw
+    }
+
+    fn state(&self) -> u64 {
+        self.position as u64
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.position = state as usize;
+    }
+}
+
+// ============================================================================ 
+// SANITY POLICY
+// ============================================================================ 
+
+/// Everything that drains (or, when negative, restores) sanity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SanityCost {
+    Token,
+    Statement,
+    LoopIteration,
+    RiftIteration,
+    LoopOverflow,
+    RealityFrays,
+    RiftExpression,
+    Infect,
+    Whisper,
+    DivisionByZero,
+    Anchor,
+    Omen,
+    TrueName,
+}
+
+impl SanityCost {
+    const ALL: [SanityCost; 13] = [
+        SanityCost::Token,
+        SanityCost::Statement,
+        SanityCost::LoopIteration,
+        SanityCost::RiftIteration,
+        SanityCost::LoopOverflow,
+        SanityCost::RealityFrays,
+        SanityCost::RiftExpression,
+        SanityCost::Infect,
+        SanityCost::Whisper,
+        SanityCost::DivisionByZero,
+        SanityCost::Anchor,
+        SanityCost::Omen,
+        SanityCost::TrueName,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SanityCost::Token => "token",
+            SanityCost::Statement => "statement",
+            SanityCost::LoopIteration => "loop_iteration",
+            SanityCost::RiftIteration => "rift_iteration",
+            SanityCost::LoopOverflow => "loop_overflow",
+            SanityCost::RealityFrays => "reality_frays",
+            SanityCost::RiftExpression => "rift_expression",
+            SanityCost::Infect => "infect",
+            SanityCost::Whisper => "whisper",
+            SanityCost::DivisionByZero => "division_by_zero",
+            SanityCost::Anchor => "anchor",
+            SanityCost::Omen => "omen",
+            SanityCost::TrueName => "true_name",
+        }
+    }
+
+    fn standard(self) -> f64 {
+        match self {
+            SanityCost::Token => 0.15,
+            SanityCost::Statement => 0.08,
+            SanityCost::LoopIteration => 0.5,
+            SanityCost::RiftIteration => 1.0,
+            SanityCost::LoopOverflow => 20.0,
+            SanityCost::RealityFrays => 2.0,
+            SanityCost::RiftExpression => 2.0,
+            SanityCost::Infect => 3.0,
+            SanityCost::Whisper => 5.0,
+            SanityCost::DivisionByZero => 10.0,
+            SanityCost::Anchor => -10.0,
+            SanityCost::Omen => 0.5,
+            SanityCost::TrueName => 25.0,
+        }
+    }
+}
+
+/// Sanity levels below which each family of horrors begins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SanityThreshold {
+    /// Echoes gain a prefix and syntax errors turn ominous.
+    Murmur,
+    /// Numbers drift, conditions flip and temporal echoes bleed through.
+    Drift,
+    /// Rift loops stop listening to their condition.
+    Critical,
+    /// Phantoms spawn and echoes distort.
+    Phantom,
+    /// The crossroads warning appears.
+    Crossroads,
+    /// Every check whispers the warning.
+    Abyss,
+}
+
+impl SanityThreshold {
+    const ALL: [SanityThreshold; 6] = [
+        SanityThreshold::Murmur,
+        SanityThreshold::Drift,
+        SanityThreshold::Critical,
+        SanityThreshold::Phantom,
+        SanityThreshold::Crossroads,
+        SanityThreshold::Abyss,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SanityThreshold::Murmur => "murmur",
+            SanityThreshold::Drift => "drift",
+            SanityThreshold::Critical => "critical",
+            SanityThreshold::Phantom => "phantom",
+            SanityThreshold::Crossroads => "crossroads",
+            SanityThreshold::Abyss => "abyss",
+        }
+    }
+
+    fn standard(self) -> f64 {
+        match self {
+            SanityThreshold::Murmur => 50.0,
+            SanityThreshold::Drift => 40.0,
+            SanityThreshold::Critical => 30.0,
+            SanityThreshold::Phantom => 20.0,
+            SanityThreshold::Crossroads => 15.0,
+            SanityThreshold::Abyss => 5.0,
+        }
+    }
+}
+
+/// Tunes how quickly the King's attention wears a program down.
+pub trait SanityPolicy {
+    fn cost(&self, cost: SanityCost) -> f64 {
+        cost.standard()
+    }
+
+    fn threshold(&self, threshold: SanityThreshold) -> f64 {
+        threshold.standard()
+    }
+}
+
+/// The original costs and thresholds.
+pub struct StandardPolicy;
+
+impl SanityPolicy for StandardPolicy {}
+
+/// Half the costs, twice the restoration, and horrors that wait for lower sanity.
+pub struct GentlePolicy;
+
+impl SanityPolicy for GentlePolicy {
+    fn cost(&self, cost: SanityCost) -> f64 {
+        let standard = cost.standard();
+        if standard < 0.0 { standard * 2.0 } else { standard * 0.5 }
+    }
+
+    fn threshold(&self, threshold: SanityThreshold) -> f64 {
+        threshold.standard() * 0.75
+    }
+}
+
+/// Double the costs, half the restoration, and horrors that arrive early.
+pub struct NightmarePolicy;
+
+impl SanityPolicy for NightmarePolicy {
+    fn cost(&self, cost: SanityCost) -> f64 {
+        let standard = cost.standard();
+        if standard < 0.0 { standard * 0.5 } else { standard * 2.0 }
+    }
+
+    fn threshold(&self, threshold: SanityThreshold) -> f64 {
+        (threshold.standard() * 1.5).min(100.0)
+    }
+}
+
+/// Resolves a preset name ("gentle", "standard" or "nightmare").
+pub fn sanity_policy(name: &str) -> Result<Box<dyn SanityPolicy>, String> {
+    match name {
+        "gentle" => Ok(Box::new(GentlePolicy)),
+        "standard" => Ok(Box::new(StandardPolicy)),
+        "nightmare" => Ok(Box::new(NightmarePolicy)),
+        _ => Err(format!("Unknown sanity policy '{}'", name)),
+    }
+}
+
+// ============================================================================ 
+// LIMITS
+// ============================================================================ 
+
+/// Runaway protection. Sanity is the narrative brake; these are the hard ones.
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    /// Statements a single run may execute; `None` never runs dry.
+    fuel: Option<u64>,
+    /// Acts active at once, however they recurse.
+    max_call_depth: usize,
+    /// Statements nested inside one another within a single act or the top level.
+    max_nesting: usize,
+    /// Iterations before a Hastur loop cries out and breaks.
+    max_loop_iterations: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { fuel: None, max_call_depth: 100, max_nesting: 100, max_loop_iterations: 1000 }
+    }
+}
+
 // ============================================================================ 
 // LEXER
 // ============================================================================ 
 
+/// Where a token or statement starts in the source. Both counts start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Span {
+    line: u32,
+    column: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Spanned {
+    token: Token,
+    span: Span,
+}
+
+/// A `#` comment. The lexer keeps these aside as trivia, so the parser never sees them
+/// and the formatter can put them back.
+#[derive(Debug, Clone, PartialEq)]
+struct Comment {
+    span: Span,
+    /// Everything after the `#`, without trailing whitespace.
+    text: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum Token {
     Act, Scene, Mask, Echo, Hastur, Cassilda, Carcosa,
     Pallid, Yellow, Tattered,
     Rewrite, Remember, Forget,
     Superpose, Collapse, Infect, Whisper, Manifest, Entangle, Anchor, Rift,
+    Before, During, Echoes,
+    Peek, Oldest, Unearth, Fragments, Purge,
+    Assert, Trial,
     Becomes, Whispers, Screams, Ascending, Descending,
     Merged, Torn, Reflected, Shattered,
     Identifier(String),
@@ -58,21 +449,34 @@ struct Lexer {
     position: usize,
     current_char: Option<char>,
     corruption_level: f64,
+    line: u32,
+    column: u32,
+    /// Comments met so far, in source order.
+    comments: Vec<Comment>,
 }
 
 impl Lexer {
     fn new(input: &str) -> Self {
         let chars: Vec<char> = input.chars().collect();
-        let current = chars.get(0).copied();
+        let current = chars.first().copied();
         Lexer {
             input: chars,
             position: 0,
             current_char: current,
             corruption_level: 0.0,
+            line: 1,
+            column: 1,
+            comments: Vec::new(),
         }
     }
     
     fn advance(&mut self) {
+        if self.current_char == Some('\n') {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         self.position += 1;
         self.current_char = self.input.get(self.position).copied();
         self.corruption_level += 0.001;
@@ -84,12 +488,16 @@ impl Lexer {
         }
     }
     
-    fn skip_comment(&mut self) {
-        if self.current_char == Some('#') {
-            while self.current_char.is_some() && self.current_char != Some('\n') {
-                self.advance();
-            }
+    fn read_comment(&mut self) {
+        let span = self.span();
+        let mut text = String::new();
+        self.advance();
+        while let Some(c) = self.current_char {
+            if c == '\n' { break; }
+            text.push(c);
+            self.advance();
         }
+        self.comments.push(Comment { span, text: text.trim_end().to_string() });
     }
     
     fn read_number(&mut self) -> f64 {
@@ -152,69 +560,99 @@ impl Lexer {
             "entangle" => Token::Entangle,
             "anchor" => Token::Anchor,
             "rift" => Token::Rift,
+            "before" => Token::Before,
+            "during" => Token::During,
+            "echoes" => Token::Echoes,
+            "peek" => Token::Peek,
+            "oldest" => Token::Oldest,
+            "unearth" => Token::Unearth,
+            "fragments" => Token::Fragments,
+            "purge" => Token::Purge,
+            "assert" => Token::Assert,
+            "trial" => Token::Trial,
             _ => Token::Identifier(id.to_string()),
         }
     }
     
-    fn tokenize(&mut self) -> Vec<Token> {
+    fn span(&self) -> Span {
+        Span { line: self.line, column: self.column }
+    }
+    
+    fn tokenize(&mut self) -> Vec<Spanned> {
         let mut tokens = Vec::new();
         loop {
             self.skip_whitespace();
-            if self.current_char.is_none() {
-                tokens.push(Token::Eof);
-                break;
-            }
-            if self.current_char == Some('#') {
-                self.skip_comment();
-                continue;
-            }
-            match self.current_char.unwrap() {
-                '(' => { tokens.push(Token::LParen); self.advance(); } 
-                ')' => { tokens.push(Token::RParen); self.advance(); } 
-                '{' => { tokens.push(Token::LBrace); self.advance(); } 
-                '}' => { tokens.push(Token::RBrace); self.advance(); } 
-                ',' => { tokens.push(Token::Comma); self.advance(); } 
-                ';' => { tokens.push(Token::Semicolon); self.advance(); } 
-                '+' => { tokens.push(Token::Merged); self.advance(); } 
-                '*' => { tokens.push(Token::Reflected); self.advance(); } 
-                '/' => { tokens.push(Token::Shattered); self.advance(); } 
-                '"' => tokens.push(Token::String(self.read_string())), 
-                '-' => {
-                    self.advance();
-                    if self.current_char == Some('>') {
-                        self.advance();
-                        tokens.push(Token::Becomes);
-                    } else {
-                        tokens.push(Token::Torn);
+            let span = self.span();
+            match self.current_char {
+                None => {
+                    tokens.push(Spanned { token: Token::Eof, span });
+                    break;
+                }
+                Some('#') => self.read_comment(),
+                // Stray characters are skipped without producing a token.
+                Some(_) => {
+                    if let Some(token) = self.scan() {
+                        tokens.push(Spanned { token, span });
                     }
                 }
-                '=' => {
+            }
+        }
+        tokens
+    }
+
+    /// Reads the token starting at the current character, which must not be
+    /// whitespace or a comment. `None` if it starts no token.
+    fn scan(&mut self) -> Option<Token> {
+        let token = match self.current_char? {
+            '(' => { self.advance(); Token::LParen }
+            ')' => { self.advance(); Token::RParen }
+            '{' => { self.advance(); Token::LBrace }
+            '}' => { self.advance(); Token::RBrace }
+            ',' => { self.advance(); Token::Comma }
+            ';' => { self.advance(); Token::Semicolon }
+            '+' => { self.advance(); Token::Merged }
+            '*' => { self.advance(); Token::Reflected }
+            '/' => { self.advance(); Token::Shattered }
+            '"' => Token::String(self.read_string()),
+            '-' => {
+                self.advance();
+                if self.current_char == Some('>') {
                     self.advance();
-                    if self.current_char == Some('=') {
-                        self.advance();
-                        tokens.push(Token::Whispers);
-                    } else {
-                        tokens.push(Token::Becomes);
-                    }
+                    Token::Becomes
+                } else {
+                    Token::Torn
                 }
-                '!' => {
+            }
+            '=' => {
+                self.advance();
+                if self.current_char == Some('=') {
                     self.advance();
-                    if self.current_char == Some('=') {
-                        self.advance();
-                        tokens.push(Token::Screams);
-                    }
+                    Token::Whispers
+                } else {
+                    Token::Becomes
                 }
-                '>' => { tokens.push(Token::Ascending); self.advance(); } 
-                '<' => { tokens.push(Token::Descending); self.advance(); } 
-                c if c.is_numeric() => tokens.push(Token::Number(self.read_number())), 
-                c if c.is_alphabetic() || c == '_' => {
-                    let id = self.read_identifier();
-                    tokens.push(self.get_keyword_or_identifier(&id));
+            }
+            '!' => {
+                self.advance();
+                if self.current_char != Some('=') {
+                    return None;
                 }
-                _ => self.advance(),
+                self.advance();
+                Token::Screams
             }
-        }
-        tokens
+            '>' => { self.advance(); Token::Ascending }
+            '<' => { self.advance(); Token::Descending }
+            c if c.is_numeric() => Token::Number(self.read_number()),
+            c if c.is_alphabetic() || c == '_' => {
+                let id = self.read_identifier();
+                self.get_keyword_or_identifier(&id)
+            }
+            _ => {
+                self.advance();
+                return None;
+            }
+        };
+        Some(token)
     }
 }
 
@@ -227,7 +665,7 @@ enum Expr {
     Number(f64),
     String(String),
     Boolean(bool),
-    Identifier(String),
+    Identifier(String, Slot),
     BinaryOp {
         left: Box<Expr>,
         op: BinaryOperator,
@@ -245,19 +683,68 @@ enum Expr {
     Manifest(String),
     Entangle(String, String),
     Rift(Box<Expr>),
+    Before(String, Box<Expr>),
+    During(String, Box<Expr>),
+    Echoes(String),
+    Peek(String),
+    Oldest(String),
+    Unearth(String),
+    Fragments(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum BinaryOperator {
     Add, Sub, Mul, Div,
     Eq, Neq, Gt, Lt,
 }
 
+/// Where a variable lives, worked out by the resolver before the program runs.
+/// Only the innermost frame and the globals are ever visible, so a name is either
+/// a slot of the current frame or something to look up by name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    /// Index into the innermost frame; an empty slot falls back to the global of the same name.
+    Local(u32),
+    /// Globals, and anything the resolver could not see (whispers, bleeding echoes, phantoms).
+    Dynamic,
+}
+
+/// An act's parameters and body, shared by its statement and every value that refers to it.
+#[derive(Debug)]
+pub struct ActDef {
+    arity: usize,
+    /// The parameters, followed by every name the body assigns once the resolver has run.
+    locals: Rc<[String]>,
+    body: Vec<Stmt>,
+    /// The body compiled for the VM, the first time the VM calls it.
+    chunk: OnceCell<Rc<Chunk>>,
+}
+
+impl ActDef {
+    /// The names it takes, in order.
+    pub fn params(&self) -> &[String] {
+        &self.locals[..self.arity]
+    }
+
+    fn chunk(&self) -> Rc<Chunk> {
+        self.chunk.get_or_init(|| Rc::new(compiler::compile(&self.body))).clone()
+    }
+}
+
+/// A statement, where it starts in the source and where its last token starts.
 #[derive(Debug, Clone)]
-enum Stmt {
-    Mask { name: String, value: Expr },
+struct Stmt {
+    kind: StmtKind,
+    span: Span,
+    end: Span,
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+enum StmtKind {
+    Mask { name: String, slot: Slot, value: Expr },
     Echo(Expr),
-    Scene(Vec<Stmt>),
+    Scene { body: Vec<Stmt>, locals: Rc<[String]> },
     Hastur {
         condition: Expr, 
         body: Vec<Stmt>,
@@ -269,17 +756,19 @@ enum Stmt {
         else_branch: Option<Vec<Stmt>>,
     },
     Carcosa(Option<Expr>),
-    Act {
-        name: String, 
-        params: Vec<String>, 
-        body: Vec<Stmt>,
-    },
+    Act { name: String, act: Rc<ActDef> },
     Rewrite { target: String },
-    Remember(String),
+    Remember { name: String, value: Option<Expr> },
+    Purge(String),
     Forget(String),
     Infect(String),
     Whisper(String),
     Anchor,
+    /// Fails the run unless its condition holds.
+    Assert(Expr),
+    /// A named test, skipped by ordinary runs. The test runner runs each top-level
+    /// trial on its own, after the rest of the program.
+    Trial { name: String, body: Vec<Stmt> },
     ExprStmt(Expr),
 }
 
@@ -288,27 +777,36 @@ enum Stmt {
 // ============================================================================ 
 
 struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<Spanned>,
     pos: usize,
     sanity: f64,
+    token_cost: f64,
+    murmur: f64,
 }
 
 impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
+    fn with_policy(tokens: Vec<Spanned>, policy: &dyn SanityPolicy) -> Self {
         Parser {
             tokens,
             pos: 0,
             sanity: 100.0,
+            token_cost: policy.cost(SanityCost::Token),
+            murmur: policy.threshold(SanityThreshold::Murmur),
         }
     }
     
     fn current(&self) -> &Token {
-        self.tokens.get(self.pos).unwrap_or(&Token::Eof)
+        self.tokens.get(self.pos).map_or(&Token::Eof, |t| &t.token)
+    }
+
+    /// Where the current token starts; past the end, where the last one did.
+    fn span(&self) -> Span {
+        self.tokens.get(self.pos).or(self.tokens.last()).map_or(Span::default(), |t| t.span)
     }
     
     fn advance(&mut self) {
         self.pos += 1;
-        self.sanity -= 0.15;
+        self.sanity -= self.token_cost;
     }
     
     fn expect(&mut self, token: Token) -> Result<(), String> {
@@ -321,7 +819,7 @@ impl Parser {
     }
     
     fn generate_error(&self) -> String {
-        if self.sanity < 50.0 {
+        if self.sanity < self.murmur {
             "The King in Yellow watches your code...".to_string()
         } else {
             "Syntax Error".to_string()
@@ -339,7 +837,8 @@ impl Parser {
     }
     
     fn parse_statement(&mut self) -> Result<Stmt, String> {
-        match self.current().clone() {
+        let span = self.span();
+        let kind = match self.current().clone() {
             Token::Mask => self.parse_mask(),
             Token::Echo => self.parse_echo(),
             Token::Scene => self.parse_scene(),
@@ -349,42 +848,48 @@ impl Parser {
             Token::Act => self.parse_act(),
             Token::Rewrite => self.parse_rewrite_stmt(),
             Token::Remember => self.parse_remember(),
+            Token::Purge => self.parse_purge(),
             Token::Forget => self.parse_forget(),
             Token::Infect => self.parse_infect(),
             Token::Whisper => self.parse_whisper(),
+            Token::Assert => self.parse_assert(),
+            Token::Trial => self.parse_trial(),
             Token::Anchor => self.parse_anchor(),
             Token::Rift => self.parse_rift(),
             _ => {
                 let expr = self.parse_expression()?;
                 self.expect(Token::Semicolon)?;
-                Ok(Stmt::ExprStmt(expr))
+                Ok(StmtKind::ExprStmt(expr))
             }
-        }
+        }?;
+        // Parsing a statement always consumes at least one token.
+        let end = self.tokens.get(self.pos - 1).or(self.tokens.last()).map_or(span, |t| t.span);
+        Ok(Stmt { kind, span, end })
     }
     
-    fn parse_mask(&mut self) -> Result<Stmt, String> {
+    fn parse_mask(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(name) = self.current().clone() {
             self.advance();
             self.expect(Token::Becomes)?;
             let value = self.parse_expression()?;
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Mask { name, value })
+            Ok(StmtKind::Mask { name, slot: Slot::Dynamic, value })
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_echo(&mut self) -> Result<Stmt, String> {
+    fn parse_echo(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::LParen)?;
         let expr = self.parse_expression()?;
         self.expect(Token::RParen)?;
         self.expect(Token::Semicolon)?;
-        Ok(Stmt::Echo(expr))
+        Ok(StmtKind::Echo(expr))
     }
     
-    fn parse_scene(&mut self) -> Result<Stmt, String> {
+    fn parse_scene(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::LBrace)?;
         let mut body = Vec::new();
@@ -394,10 +899,10 @@ impl Parser {
         }
         
         self.expect(Token::RBrace)?;
-        Ok(Stmt::Scene(body))
+        Ok(StmtKind::Scene { body, locals: Rc::from(Vec::new()) })
     }
     
-    fn parse_hastur(&mut self) -> Result<Stmt, String> {
+    fn parse_hastur(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::LParen)?;
         let condition = self.parse_expression()?;
@@ -410,10 +915,10 @@ impl Parser {
         }
         
         self.expect(Token::RBrace)?;
-        Ok(Stmt::Hastur { condition, body, is_rift: false })
+        Ok(StmtKind::Hastur { condition, body, is_rift: false })
     }
     
-    fn parse_rift(&mut self) -> Result<Stmt, String> {
+    fn parse_rift(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::LParen)?;
         let condition = self.parse_expression()?;
@@ -426,10 +931,10 @@ impl Parser {
         }
         
         self.expect(Token::RBrace)?;
-        Ok(Stmt::Hastur { condition, body, is_rift: true })
+        Ok(StmtKind::Hastur { condition, body, is_rift: true })
     }
     
-    fn parse_cassilda(&mut self) -> Result<Stmt, String> {
+    fn parse_cassilda(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::LParen)?;
         let condition = self.parse_expression()?;
@@ -442,10 +947,10 @@ impl Parser {
         }
         
         self.expect(Token::RBrace)?;
-        Ok(Stmt::Cassilda { condition, then_branch, else_branch: None })
+        Ok(StmtKind::Cassilda { condition, then_branch, else_branch: None })
     }
     
-    fn parse_carcosa(&mut self) -> Result<Stmt, String> {
+    fn parse_carcosa(&mut self) -> Result<StmtKind, String> {
         self.advance();
         let value = if self.current() == &Token::Semicolon {
             None
@@ -453,10 +958,10 @@ impl Parser {
             Some(self.parse_expression()?)
         };
         self.expect(Token::Semicolon)?;
-        Ok(Stmt::Carcosa(value))
+        Ok(StmtKind::Carcosa(value))
     }
     
-    fn parse_act(&mut self) -> Result<Stmt, String> {
+    fn parse_act(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(name) = self.current().clone() {
             self.advance();
@@ -478,71 +983,124 @@ impl Parser {
                 body.push(self.parse_statement()?);
             }
             self.expect(Token::RBrace)?;
-            Ok(Stmt::Act { name, params, body })
+            let act = ActDef { arity: params.len(), locals: params.into(), body, chunk: OnceCell::new() };
+            Ok(StmtKind::Act { name, act: Rc::new(act) })
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_rewrite_stmt(&mut self) -> Result<Stmt, String> {
+    fn parse_rewrite_stmt(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(target) = self.current().clone() {
             self.advance();
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Rewrite { target })
+            Ok(StmtKind::Rewrite { target })
+        } else {
+            Err(self.generate_error())
+        }
+    }
+    
+    fn parse_remember(&mut self) -> Result<StmtKind, String> {
+        self.advance();
+        if let Token::Identifier(name) = self.current().clone() {
+            self.advance();
+            let value = if self.current() == &Token::Becomes {
+                self.advance();
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            self.expect(Token::Semicolon)?;
+            Ok(StmtKind::Remember { name, value })
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_remember(&mut self) -> Result<Stmt, String> {
+    fn parse_purge(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(name) = self.current().clone() {
             self.advance();
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Remember(name))
+            Ok(StmtKind::Purge(name))
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_forget(&mut self) -> Result<Stmt, String> {
+    fn parse_forget(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(name) = self.current().clone() {
             self.advance();
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Forget(name))
+            Ok(StmtKind::Forget(name))
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_infect(&mut self) -> Result<Stmt, String> {
+    fn parse_infect(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::Identifier(name) = self.current().clone() {
             self.advance();
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Infect(name))
+            Ok(StmtKind::Infect(name))
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_whisper(&mut self) -> Result<Stmt, String> {
+    fn parse_whisper(&mut self) -> Result<StmtKind, String> {
         self.advance();
         if let Token::String(code) = self.current().clone() {
             self.advance();
             self.expect(Token::Semicolon)?;
-            Ok(Stmt::Whisper(code))
+            Ok(StmtKind::Whisper(code))
         } else {
             Err(self.generate_error())
         }
     }
     
-    fn parse_anchor(&mut self) -> Result<Stmt, String> {
+    fn parse_assert(&mut self) -> Result<StmtKind, String> {
+        self.advance();
+        let condition = self.parse_expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(StmtKind::Assert(condition))
+    }
+
+    fn parse_trial(&mut self) -> Result<StmtKind, String> {
+        self.advance();
+        let Token::String(name) = self.current().clone() else {
+            return Err(self.generate_error());
+        };
+        self.advance();
+        self.expect(Token::LBrace)?;
+        let mut body = Vec::new();
+        while self.current() != &Token::RBrace && self.current() != &Token::Eof {
+            body.push(self.parse_statement()?);
+        }
+        self.expect(Token::RBrace)?;
+        Ok(StmtKind::Trial { name, body })
+    }
+
+    fn parse_anchor(&mut self) -> Result<StmtKind, String> {
         self.advance();
         self.expect(Token::Semicolon)?;
-        Ok(Stmt::Anchor)
+        Ok(StmtKind::Anchor)
+    }
+    
+    /// Parses `keyword(name)` for built-ins that operate on a name rather than a value.
+    fn parse_named_operand(&mut self) -> Result<String, String> {
+        self.advance();
+        self.expect(Token::LParen)?;
+        if let Token::Identifier(name) = self.current().clone() {
+            self.advance();
+            self.expect(Token::RParen)?;
+            Ok(name)
+        } else {
+            Err(self.generate_error())
+        }
     }
     
     fn parse_expression(&mut self) -> Result<Expr, String> {
@@ -652,7 +1210,7 @@ impl Parser {
                     self.expect(Token::RParen)?; 
                     Ok(Expr::Call { name, args })
                 } else {
-                    Ok(Expr::Identifier(name))
+                    Ok(Expr::Identifier(name, Slot::Dynamic))
                 }
             }
             Token::LParen => {
@@ -723,6 +1281,30 @@ impl Parser {
                 self.expect(Token::RParen)?; 
                 Ok(Expr::Rift(Box::new(expr)))
             }
+            Token::Before | Token::During => {
+                let steps_back = self.current() == &Token::Before;
+                self.advance();
+                self.expect(Token::LParen)?;
+                let name = if let Token::Identifier(n) = self.current().clone() {
+                    self.advance();
+                    n
+                } else {
+                    return Err(self.generate_error());
+                };
+                self.expect(Token::Comma)?;
+                let when = self.parse_expression()?;
+                self.expect(Token::RParen)?;
+                if steps_back {
+                    Ok(Expr::Before(name, Box::new(when)))
+                } else {
+                    Ok(Expr::During(name, Box::new(when)))
+                }
+            }
+            Token::Echoes => Ok(Expr::Echoes(self.parse_named_operand()?)),
+            Token::Peek => Ok(Expr::Peek(self.parse_named_operand()?)),
+            Token::Oldest => Ok(Expr::Oldest(self.parse_named_operand()?)),
+            Token::Unearth => Ok(Expr::Unearth(self.parse_named_operand()?)),
+            Token::Fragments => Ok(Expr::Fragments(self.parse_named_operand()?)),
             Token::Rewrite => {
                 self.advance();
                 let target = self.parse_primary()?;
@@ -739,13 +1321,20 @@ impl Parser {
 // VALUE
 // ============================================================================ 
 
+/// Anything a Yellow expression can evaluate to, and what host acts take and give back.
 #[derive(Clone, Debug)]
-enum Value {
+pub enum Value {
     Number(f64),
     String(String),
     Boolean(bool),
+    /// Pallid.
     Null,
-    Function { params: Vec<String>, body: Vec<Stmt> },
+    /// An act the program defined.
+    Function { act: Rc<ActDef> },
+    /// A built-in act, or one the host registered.
+    Native(Rc<Native>),
+    /// What `echoes` read back: past values of a variable, oldest first.
+    History(Vec<Value>),
     Quantum(Box<QuantumState>),
 }
 
@@ -756,7 +1345,8 @@ impl fmt::Display for Value {
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Null => write!(f, "pallid"),
-            Value::Function { .. } => write!(f, "<act>"),
+            Value::Function { .. } | Value::Native(_) => write!(f, "<act>"),
+            Value::History(vals) => write!(f, "[{}]", vals.iter().map(shown).collect::<Vec<_>>().join(", ")),
             Value::Quantum(qs) => match **qs {
                 QuantumState::Superposition(ref vals) => {
                     write!(f, "<superposed: {} possibilities>", vals.len())
@@ -765,47 +1355,153 @@ impl fmt::Display for Value {
                     write!(f, "<entangled with {}>", name)
                 }
                 QuantumState::Phantom => write!(f, "<phantom>"),
-                QuantumState::Collapsed(ref v) => write!(f, "{}", v),
             },
         }
     }
 }
 
+/// A value as an assertion reports it: like `echo`, but strings keep their quotes.
+fn shown(val: &Value) -> String {
+    match val {
+        Value::String(s) => format!("\"{}\"", s),
+        val => val.to_string(),
+    }
+}
+
 // ============================================================================ 
 // INTERPRETER
 // ============================================================================ 
 
-struct Interpreter {
-    global_env: BTreeMap<String, Value>,
-    call_stack: Vec<BTreeMap<String, Value>>,
-    sanity: f64,
-    execution_depth: usize,
-    memory_fragments: BTreeMap<String, VecDeque<Value>>,
-    temporal_echoes: Vec<TemporalEcho>,
-    infections: BTreeMap<String, Infection>,
-    entropy: u64,
-    reality_stable: bool,
-    phantom_variables: BTreeMap<String, Value>,
-    generated_code: Vec<String>,
-    whisper_count: usize,
-    max_whispers: usize,
-    forbidden_patterns: Vec<String>,
-    rng_state: u64,
+/// A call or scene frame: the slots the resolver laid out, plus any names created at runtime.
+#[derive(Clone)]
+struct Scope {
+    names: Rc<[String]>,
+    slots: Vec<Option<Value>>,
+    spilled: BTreeMap<String, Value>,
 }
 
-impl Interpreter {
-    fn new() -> Self {
-        let seed = 123456789;
-        Interpreter {
-            global_env: BTreeMap::new(),
-            call_stack: Vec::new(),
-            sanity: 100.0,
-            execution_depth: 0,
-            memory_fragments: BTreeMap::new(),
-            temporal_echoes: Vec::new(),
-            infections: BTreeMap::new(),
-            entropy: 0,
-            reality_stable: true,
+impl Scope {
+    fn new(names: Rc<[String]>) -> Self {
+        Scope { slots: vec![None; names.len()], names, spilled: BTreeMap::new() }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        match self.position(name) {
+            Some(idx) => self.slots[idx].as_ref(),
+            None => self.spilled.get(name),
+        }
+    }
+
+    fn load(&self, slot: Slot, name: &str) -> Option<&Value> {
+        match slot {
+            Slot::Local(idx) => match self.slots.get(idx as usize) {
+                Some(cell) => cell.as_ref(),
+                None => self.get(name),
+            },
+            Slot::Dynamic => self.get(name),
+        }
+    }
+
+    fn store(&mut self, slot: Slot, name: &str, value: Value) {
+        let idx = match slot {
+            Slot::Local(idx) if (idx as usize) < self.slots.len() => Some(idx as usize),
+            _ => self.position(name),
+        };
+        match idx {
+            Some(idx) => self.slots[idx] = Some(value),
+            None => {
+                self.spilled.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        match self.position(name) {
+            Some(idx) => self.slots[idx] = None,
+            None => {
+                self.spilled.remove(name);
+            }
+        }
+    }
+
+    /// Every name holding a value, sorted like the globals.
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.names.iter().zip(&self.slots)
+            .filter(|(_, cell)| cell.is_some())
+            .map(|(name, _)| name.clone())
+            .chain(self.spilled.keys().cloned())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+/// Longest whisper the interpreter agrees to run.
+const MAX_WHISPER_LENGTH: usize = 1000;
+/// Most statements a whisper may hold.
+const MAX_WHISPER_STATEMENTS: usize = 10;
+
+struct Interpreter {
+    global_env: BTreeMap<String, Value>,
+    call_stack: Vec<Scope>,
+    sanity: f64,
+    execution_depth: usize,
+    memory_fragments: BTreeMap<String, VecDeque<Value>>,
+    fragment_capacity: usize,
+    temporal_echoes: VecDeque<TemporalEcho>,
+    echo_retention: EchoRetention,
+    infections: BTreeMap<String, Infection>,
+    entropy: u64,
+    reality_stable: bool,
+    phantom_variables: BTreeMap<String, Value>,
+    generated_code: Vec<String>,
+    whisper_count: usize,
+    max_whispers: usize,
+    forbidden_patterns: Vec<String>,
+    rng: Box<dyn Rng>,
+    /// Shared, so trials can run each fresh session under the same policy.
+    policy: Rc<dyn SanityPolicy>,
+    /// Lucid runs keep counting sanity but suppress every horror effect, so programs are deterministic.
+    lucid: bool,
+    limits: Limits,
+    /// Statements executed by the current run, charged against `limits.fuel`.
+    fuel_used: u64,
+    /// Whether the current run ended because `limits.fuel` was spent.
+    out_of_fuel: bool,
+    /// Collects every draw and what it caused while a run is being recorded.
+    recorder: Option<Box<Recorder>>,
+    call_depth: usize,
+    /// Sanity at the previous statement, to notice thresholds being crossed.
+    sanity_seen: f64,
+}
+
+impl Interpreter {
+    fn new() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    fn with_seed(seed: u64) -> Self {
+        Self::with_rng(Box::new(XorShift::new(seed)))
+    }
+
+    pub(crate) fn with_rng(rng: Box<dyn Rng>) -> Self {
+        Interpreter {
+            global_env: builtins::globals(),
+            call_stack: Vec::new(),
+            sanity: 100.0,
+            execution_depth: 0,
+            memory_fragments: BTreeMap::new(),
+            fragment_capacity: 10,
+            temporal_echoes: VecDeque::new(),
+            echo_retention: EchoRetention::default(),
+            infections: BTreeMap::new(),
+            entropy: 0,
+            reality_stable: true,
             phantom_variables: BTreeMap::new(),
             generated_code: Vec::new(),
             whisper_count: 0,
@@ -814,101 +1510,147 @@ impl Interpreter {
                 "whisper".to_string(), "infect".to_string(), "rift".to_string(),
                 "carcosa".to_string(), "system".to_string(), "creative".to_string(), "spectator".to_string()
             ],
-            rng_state: seed,
+            rng,
+            policy: Rc::new(StandardPolicy),
+            lucid: false,
+            limits: Limits::default(),
+            fuel_used: 0,
+            out_of_fuel: false,
+            recorder: None,
+            call_depth: 0,
+            sanity_seen: 100.0,
+        }
+    }
+
+    fn pseudo_random(&mut self) -> f64 {
+        let draw = self.rng.next_f64();
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.draw(draw);
         }
+        draw
     }
 
-    fn log(&self, msg: &str, buffer: &mut String) {
-        if buffer.len() < 10000 { // Safety cap on output size
-            buffer.push_str(msg);
-            buffer.push('\n');
+    /// Notes what the latest draw made happen, when the run is being recorded.
+    fn note(&mut self, effect: impl FnOnce(usize) -> Effect) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.note(effect);
         }
     }
     
-    fn pseudo_random(&mut self) -> f64 {
-        let mut x = self.rng_state;
-        x ^= x << 13;
-        x ^= x >> 7;
-        x ^= x << 17;
-        self.rng_state = x;
-        (x % 10000) as f64 / 10000.0
+    fn drain_sanity(&mut self, cost: SanityCost) {
+        self.sanity -= self.policy.cost(cost);
+    }
+
+    fn below(&self, threshold: SanityThreshold) -> bool {
+        self.sanity < self.policy.threshold(threshold)
+    }
+
+    /// Whether the horrors tied to `threshold` are active right now.
+    fn haunted(&self, threshold: SanityThreshold) -> bool {
+        !self.lucid && self.below(threshold)
+    }
+
+    /// Picks one of `len` quantum states; lucid runs always observe the first.
+    fn choose(&mut self, len: usize) -> usize {
+        if self.lucid {
+            return 0;
+        }
+        let choice = (self.pseudo_random() * len as f64) as usize;
+        self.note(|draw| Effect::Collapse { draw, choice, of: len });
+        choice
     }
     
-    fn temporal_drift(&self) -> f64 {
-        (self.entropy as f64 / 100.0).tanh() * (1.0 - self.sanity / 100.0)
+    fn temporal_drift(&mut self) -> f64 {
+        if self.lucid {
+            return 0.0;
+        }
+        let value = (self.entropy as f64 / 100.0).tanh() * (1.0 - self.sanity / 100.0);
+        if value != 0.0 {
+            self.note(|_| Effect::Drift { value });
+        }
+        value
     }
     
-    fn sanity_check(&mut self, buffer: &mut String) -> bool {
+    fn sanity_check(&mut self, buffer: &mut Events) -> bool {
         if self.sanity.is_nan() {
             self.sanity = 0.0;
-            self.log("⚠ Sanity is NaN... assuming 0.", buffer);
+            buffer.warn("⚠ Sanity is NaN... assuming 0.");
         }
 
         if self.sanity < 0.0 {
-            self.log("\n[THE YELLOW SIGN HAS BEEN REVEALED]", buffer);
-            self.log("Reality dissolves...", buffer);
+            buffer.warn("\n[THE YELLOW SIGN HAS BEEN REVEALED]");
+            buffer.narrate("Reality dissolves...");
             return false;
         }
 
-        if self.sanity < 15.0 && self.pseudo_random() > 0.7 {
-            self.log("\n⚠ DON'T TURN LEFT.", buffer);
+        if self.haunted(SanityThreshold::Crossroads) && self.pseudo_random() > 0.7 {
+            buffer.warn("\n⚠ DON'T TURN LEFT.");
         }
 
-        if self.sanity < 5.0 {
-            buffer.push_str(" don't turn left");
+        if self.haunted(SanityThreshold::Abyss) {
+            buffer.emit(Event::Murmur { text: " don't turn left".to_string() });
         }
         
-        if self.sanity < 20.0 && self.pseudo_random() > 0.95 {
+        if self.haunted(SanityThreshold::Phantom) && self.pseudo_random() > 0.95 {
             self.spawn_phantom(buffer);
         }
         
-        if self.sanity < 40.0 && !self.temporal_echoes.is_empty() {
+        if self.haunted(SanityThreshold::Drift) && !self.temporal_echoes.is_empty() {
             self.manifest_temporal_echo(buffer);
         }
         true
     }
     
-    fn spawn_phantom(&mut self, buffer: &mut String) {
+    fn spawn_phantom(&mut self, buffer: &mut Events) {
         let phantom_names = vec![
             "shadow", "echo", "whisper", "void", "fragment",
             "Avery", "Derlord", "The_Oasis", "Bedrock"
         ];
         let idx = (self.pseudo_random() * phantom_names.len() as f64) as usize;
         let name = phantom_names[idx];
+        self.note(|draw| Effect::Phantom { draw, name: name.to_string() });
 
         let value = Value::Quantum(Box::new(QuantumState::Phantom));
         self.phantom_variables.insert(name.to_string(), value);
 
-        self.log(&format!("⚠ Phantom variable '{}' manifests from the void...", name), buffer);
+        buffer.emit(Event::PhantomSpawned { name: name.to_string() });
     }
     
-    fn manifest_temporal_echo(&mut self, buffer: &mut String) {
-        if let Some(echo) = self.temporal_echoes.pop() {
+    fn manifest_temporal_echo(&mut self, buffer: &mut Events) {
+        if let Some(echo) = self.temporal_echoes.pop_back() {
             if echo.stability > 0.3 {
-                self.set_var(echo.variable_name.clone(), (*echo.ghost_value).clone());
-                self.log(&format!("Temporal echo of '{}' bleeds through from past execution", echo.variable_name), buffer);
+                self.set_var(&echo.variable_name, (*echo.ghost_value).clone());
+                buffer.narrate(format!("Temporal echo of '{}' bleeds through from past execution", echo.variable_name));
             }
         }
     }
     
     fn get_var(&self, name: &str) -> Option<Value> {
-        if self.sanity < 20.0 {
+        self.lookup(name).cloned()
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.lookup_slot(Slot::Dynamic, name)
+    }
+
+    fn lookup_slot(&self, slot: Slot, name: &str) -> Option<&Value> {
+        if self.haunted(SanityThreshold::Phantom) {
             if let Some(phantom) = self.phantom_variables.get(name) {
-                return Some(phantom.clone());
+                return Some(phantom);
             }
         }
         if let Some(frame) = self.call_stack.last() {
-            if let Some(val) = frame.get(name) {
-                return Some(val.clone());
+            if let Some(val) = frame.load(slot, name) {
+                return Some(val);
             }
         }
-        if let Some(val) = self.global_env.get(name) {
-            return Some(val.clone());
-        }
-        None
+        self.global_env.get(name)
     }
     
     fn apply_infection_corruption(&mut self, name: &str, mut value: Value) -> Value {
+        if self.lucid {
+            return value;
+        }
         if let Some(infection) = self.infections.get(name).cloned() {
              value = match value {
                 Value::Number(n) => {
@@ -924,87 +1666,188 @@ impl Interpreter {
         value
     }
     
-    fn set_var(&mut self, name: String, value: Value) {
+    fn set_var(&mut self, name: &str, value: Value) {
+        self.assign(Slot::Dynamic, name, value);
+    }
+
+    fn assign(&mut self, slot: Slot, name: &str, value: Value) {
         let echo = TemporalEcho {
             timestamp: self.entropy,
-            variable_name: name.clone(),
+            variable_name: name.to_string(),
             ghost_value: Box::new(value.clone()),
             stability: self.sanity / 100.0,
         };
-        self.temporal_echoes.push(echo);
-
-        if self.temporal_echoes.len() > 50 {
-            self.temporal_echoes.drain(0..10);
-        }
+        self.temporal_echoes.push_back(echo);
+        self.trim_echoes();
 
         if let Some(frame) = self.call_stack.last_mut() {
-            frame.insert(name, value);
+            frame.store(slot, name, value);
         } else {
-            self.global_env.insert(name, value);
+            self.global_env.insert(name.to_string(), value);
         }
     }
     
-    fn execute(&mut self, statements: &Vec<Stmt>, buffer: &mut String) -> Result<Option<Value>, String> {
-        for stmt in statements {
-            // self.log(&format!("[DEBUG] Loop Sanity: {:.4}", self.sanity), buffer);
-            self.sanity -= 0.08;
-            self.entropy += 1;
-            if !self.sanity_check(buffer) { return Err("Sanity depleted".to_string()); }
-            if let Some(val) = self.execute_stmt(stmt.clone(), buffer)? { return Ok(Some(val)); }
+    fn remember(&mut self, name: String, val: Value) {
+        let capacity = self.fragment_capacity;
+        let fragments = self.memory_fragments.entry(name).or_default();
+        fragments.push_back(val);
+        while fragments.len() > capacity {
+            fragments.pop_front();
         }
-        Ok(None)
     }
-    
-    fn execute_stmt(&mut self, stmt: Stmt, buffer: &mut String) -> Result<Option<Value>, String> {
+
+    fn trim_fragments(&mut self) {
+        let capacity = self.fragment_capacity;
+        for fragments in self.memory_fragments.values_mut() {
+            while fragments.len() > capacity {
+                fragments.pop_front();
+            }
+        }
+    }
+
+    fn trim_echoes(&mut self) {
+        match self.echo_retention {
+            EchoRetention::Unbounded => {}
+            EchoRetention::Total(limit) => {
+                if self.temporal_echoes.len() > limit {
+                    let excess = self.temporal_echoes.len() - limit;
+                    self.temporal_echoes.drain(0..excess);
+                }
+            }
+            EchoRetention::PerVariable(limit) => {
+                let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+                let mut keep = vec![false; self.temporal_echoes.len()];
+                for (i, echo) in self.temporal_echoes.iter().enumerate().rev() {
+                    let count = seen.entry(echo.variable_name.as_str()).or_insert(0);
+                    if *count < limit {
+                        *count += 1;
+                        keep[i] = true;
+                    }
+                }
+                let mut flags = keep.into_iter();
+                self.temporal_echoes.retain(|_| flags.next().unwrap_or(false));
+            }
+            EchoRetention::Window(ticks) => {
+                let horizon = self.entropy.saturating_sub(ticks);
+                self.temporal_echoes.retain(|echo| echo.timestamp >= horizon);
+            }
+        }
+    }
+
+    /// The value `name` held at entropy `timestamp`: its newest echo recorded no later than that.
+    fn echo_at(&self, name: &str, timestamp: u64) -> Option<Value> {
+        self.temporal_echoes
+            .iter()
+            .rev()
+            .find(|echo| echo.variable_name == name && echo.timestamp <= timestamp)
+            .map(|echo| (*echo.ghost_value).clone())
+    }
+
+    fn timestamp(&self, when: Value) -> Result<u64, String> {
+        match when {
+            Value::Number(n) if n >= 0.0 && n.is_finite() => Ok(n as u64),
+            v => Err(format!("Time cannot flow to '{}'", v)),
+        }
+    }
+
+    /// Statement prologue shared by both engines: every statement costs sanity and advances entropy.
+    fn tick(&mut self, buffer: &mut Events) -> Result<(), String> {
+        if let Some(fuel) = self.limits.fuel {
+            if self.fuel_used >= fuel {
+                self.out_of_fuel = true;
+                return Err(format!("Out of fuel: the budget of {} statements is spent.", fuel));
+            }
+        }
+        self.fuel_used += 1;
+        self.drain_sanity(SanityCost::Statement);
+        self.entropy += 1;
+        self.note_thresholds(buffer);
+        if !self.sanity_check(buffer) { return Err("Sanity depleted".to_string()); }
+        Ok(())
+    }
+
+    /// Reports every threshold sanity has fallen through since the previous statement.
+    fn note_thresholds(&mut self, buffer: &mut Events) {
+        for threshold in SanityThreshold::ALL {
+            let level = self.policy.threshold(threshold);
+            if self.sanity_seen >= level && self.sanity < level {
+                buffer.emit(Event::SanityThreshold { threshold, sanity: self.sanity });
+            }
+        }
+        self.sanity_seen = self.sanity;
+    }
+
+    fn enter_stmt(&mut self) -> Result<(), String> {
         self.execution_depth += 1;
-        
-        if self.execution_depth > 100 {
+        if self.execution_depth > self.limits.max_nesting {
             self.execution_depth -= 1; // Unwind count before returning
-            return Err(format!("⚠ Reality fragmented: Maximum recursion depth (100) exceeded."));
+            return Err(format!("Reality fragmented: Maximum nesting depth ({}) exceeded.", self.limits.max_nesting));
         }
-        
-        let result = match stmt {
-            Stmt::Mask { name, value } => {
+        Ok(())
+    }
+
+    /// Opens a call. Recursion is bounded on its own, and the callee's statements nest from zero.
+    /// Returns the caller's nesting depth for `leave_call`.
+    fn enter_call(&mut self) -> Result<usize, String> {
+        if self.call_depth >= self.limits.max_call_depth {
+            return Err(format!("The act devours itself: Maximum call depth ({}) exceeded.", self.limits.max_call_depth));
+        }
+        self.call_depth += 1;
+        Ok(std::mem::replace(&mut self.execution_depth, 0))
+    }
+
+    fn leave_call(&mut self, depth: usize) {
+        self.call_depth -= 1;
+        self.execution_depth = depth;
+    }
+
+    fn execute(&mut self, statements: &[Stmt], buffer: &mut Events) -> Result<Option<Value>, String> {
+        for stmt in statements {
+            self.tick(buffer)?;
+            if let Some(val) = self.execute_stmt(stmt, buffer)? { return Ok(Some(val)); }
+        }
+        Ok(None)
+    }
+    
+    fn execute_stmt(&mut self, stmt: &Stmt, buffer: &mut Events) -> Result<Option<Value>, String> {
+        self.enter_stmt()?;
+        let result = self.execute_stmt_inner(stmt, buffer);
+        self.execution_depth -= 1;
+        result
+    }
+
+    fn execute_stmt_inner(&mut self, stmt: &Stmt, buffer: &mut Events) -> Result<Option<Value>, String> {
+        match &stmt.kind {
+            StmtKind::Mask { name, slot, value } => {
                 let val = self.eval_expr(value, buffer)?;
-                self.set_var(name, val);
+                self.assign(*slot, name, val);
                 Ok(None)
             }
-            Stmt::Echo(expr) => {
+            StmtKind::Echo(expr) => {
                 let val = self.eval_expr(expr, buffer)?;
-                
-                if self.sanity < 20.0 {
-                    let s = val.to_string();
-                    let distorted = self.distort_output(&s);
-                    self.log(&format!("𝔈𝔠𝔥𝔬: {}", distorted), buffer);
-                } else if self.sanity < 50.0 {
-                    self.log(&format!("Echo: {}", val), buffer);
-                } else {
-                    self.log(&format!("{}", val), buffer);
-                }
+                self.echo_value(val, buffer);
                 Ok(None)
             }
-            Stmt::Scene(body) => {
-                self.call_stack.push(BTreeMap::new());
-                let result = self.execute(&body, buffer);
+            StmtKind::Scene { body, locals } => {
+                self.call_stack.push(Scope::new(locals.clone()));
+                let result = self.execute(body, buffer);
                 self.call_stack.pop();
                 result
             }
-            Stmt::Hastur { condition, body, is_rift } => {
-                if is_rift {
+            StmtKind::Hastur { condition, body, is_rift } => {
+                if *is_rift {
                     self.execute_non_euclidean_loop(condition, body, buffer)
                 } else {
                     self.execute_normal_loop(condition, body, buffer)
                 }
             }
-                        Stmt::Cassilda { condition, then_branch, else_branch } => {
-                            let cond_val = self.eval_expr(condition, buffer)?;
-                            let drift = self.temporal_drift();
-                            let take_then = self.evaluate_condition(&cond_val, drift);
-                            if take_then { self.execute(&then_branch, buffer) } 
-                            else if let Some(else_b) = else_branch { self.execute(&else_b, buffer) } 
-                            else { Ok(None) }
-                        }
-            Stmt::Carcosa(expr) => {
+            StmtKind::Cassilda { condition, then_branch, else_branch } => {
+                let cond_val = self.eval_expr(condition, buffer)?;
+                if self.take_branch(&cond_val) { self.execute(then_branch, buffer) }
+                else if let Some(else_b) = else_branch { self.execute(else_b, buffer) }
+                else { Ok(None) }
+            }
+            StmtKind::Carcosa(expr) => {
                 let val = if let Some(e) = expr {
                     Some(self.eval_expr(e, buffer)?)
                 } else {
@@ -1012,305 +1855,477 @@ impl Interpreter {
                 };
                 Ok(val)
             }
-            Stmt::Act { name, params, body } => {
-                let val = Value::Function { params: params.clone(), body: body.clone() };
+            StmtKind::Act { name, act } => {
+                let val = Value::Function { act: act.clone() };
                 self.set_var(name, val);
                 Ok(None)
             }
-            Stmt::Rewrite { target } => {
-                if let Some(val) = self.get_var(&target) {
-                    let new_val = self.mutate_value(val);
-                    self.set_var(target.clone(), new_val);
-                    if self.entropy % 10 == 0 {
-                        self.log(&format!("⚠ Reality frays..."), buffer);
-                        self.sanity -= 2.0;
-                    }
-                }
+            StmtKind::Rewrite { target } => {
+                self.rewrite_var(target, buffer);
                 Ok(None)
             }
-            Stmt::Remember(name) => {
-                if let Some(val) = self.get_var(&name) {
-                    self.memory_fragments
-                        .entry(name.clone())
-                        .or_insert_with(VecDeque::new)
-                        .push_back(val);
-                    
-                    if let Some(fragments) = self.memory_fragments.get_mut(&name) {
-                        if fragments.len() > 10 {
-                            fragments.pop_front();
-                        }
-                    }
+            StmtKind::Remember { name, value } => {
+                let val = match value {
+                    Some(expr) => Some(self.eval_expr(expr, buffer)?),
+                    None => self.get_var(name),
+                };
+                if let Some(val) = val {
+                    self.remember(name.clone(), val);
                 }
                 Ok(None)
             }
-            Stmt::Forget(name) => {
-                if let Some(frame) = self.call_stack.last_mut() {
-                    frame.remove(&name);
-                } else {
-                    self.global_env.remove(&name);
-                }
-                self.log(&format!("Forgotten: {}... but fragments remain", name), buffer);
+            StmtKind::Purge(name) => {
+                self.purge(name, buffer);
                 Ok(None)
             }
-            Stmt::Infect(name) => {
-                let infection = Infection {
-                    source: name.clone(),
-                    virulence: 0.5 + (self.temporal_drift() * 0.5),
-                    mutation_vector: 0,
-                };
-                
-                self.infections.insert(name.clone(), infection);
-                self.log(&format!("⚠ Variable '{}' infected. Contagion spreads...", name), buffer);
-                self.sanity -= 3.0;
-                
-                self.spread_infection(&name, buffer);
+            StmtKind::Forget(name) => {
+                self.forget(name, buffer);
                 Ok(None)
             }
-            Stmt::Whisper(code) => {
-                self.whisper_count += 1;
-                if self.whisper_count > self.max_whispers {
-                    self.log("⚠ Whisper limit exceeded.", buffer);
-                    self.whisper_count -= 1;
-                    return Ok(None);
-                }
-                if code.len() > 1000 {
-                    self.log("⚠ Whisper exceeds maximum length.", buffer);
-                    self.whisper_count -= 1;
-                    return Ok(None);
-                }
-                for pattern in &self.forbidden_patterns {
-                    if code.to_lowercase().contains(&pattern.to_lowercase()) {
-                        self.log(&format!("⚠ Forbidden incantation '{}' detected.", pattern), buffer);
-                        self.whisper_count -= 1;
-                        return Ok(None);
-                    }
-                }
-
-                self.log(&format!("◈ Whisper manifests: {}", code), buffer);
-                self.generated_code.push(code.clone());
-
-                let mut lexer = Lexer::new(&code);
-                let tokens = lexer.tokenize();
-                let mut parser = Parser::new(tokens);
-
-                match parser.parse_program() {
-                    Ok(ast) => {
-                        if ast.len() > 10 {
-                            self.log("⚠ Whisper AST too complex.", buffer);
-                            self.whisper_count -= 1;
-                            return Ok(None);
-                        }
-                        self.sanity -= 5.0;
-                        self.execute(&ast, buffer)
+            StmtKind::Infect(name) => {
+                self.infect(name, buffer);
+                Ok(None)
+            }
+            StmtKind::Whisper(code) => self.whisper(code, buffer),
+            StmtKind::Anchor => {
+                self.anchor(buffer);
+                Ok(None)
+            }
+            StmtKind::Assert(condition) => {
+                match condition {
+                    Expr::BinaryOp { left, op, right } => {
+                        let l = self.eval_expr(left, buffer)?;
+                        let r = self.eval_expr(right, buffer)?;
+                        let text = printer::expression(condition);
+                        self.assert_binary(&text, l, *op, r, buffer)?;
                     }
-                    Err(_) => {
-                        self.log("⚠ Whisper fails to manifest properly", buffer);
-                        self.whisper_count -= 1;
-                        Ok(None)
+                    _ => {
+                        let val = self.eval_expr(condition, buffer)?;
+                        self.assert_value(&printer::expression(condition), val)?;
                     }
                 }
-            }
-            Stmt::Anchor => {
-                self.reality_stable = true;
-                self.sanity += 10.0;
-                if self.sanity > 100.0 {
-                    self.sanity = 100.0;
-                }
-                self.log("Reality temporarily stabilized", buffer);
                 Ok(None)
             }
-            Stmt::ExprStmt(expr) => {
+            StmtKind::Trial { .. } => Ok(None),
+            StmtKind::ExprStmt(expr) => {
                 self.eval_expr(expr, buffer)?;
                 Ok(None)
             }
+        }
+    }
+
+    fn echo_value(&mut self, val: Value, buffer: &mut Events) {
+        if self.haunted(SanityThreshold::Phantom) {
+            let s = val.to_string();
+            let distorted = self.distort_output(&s);
+            buffer.emit(Event::Echo { text: format!("𝔈𝔠𝔥𝔬: {}", distorted), distorted: true });
+        } else if self.haunted(SanityThreshold::Murmur) {
+            buffer.emit(Event::Echo { text: format!("Echo: {}", val), distorted: false });
+        } else {
+            buffer.emit(Event::Echo { text: val.to_string(), distorted: false });
+        }
+    }
+
+    /// Cassilda's branch decision, including drift and low-sanity flips.
+    fn take_branch(&mut self, cond_val: &Value) -> bool {
+        let drift = self.temporal_drift();
+        self.evaluate_condition(cond_val, drift)
+    }
+
+    fn rewrite_var(&mut self, target: &str, buffer: &mut Events) {
+        if let Some(val) = self.get_var(target) {
+            let new_val = self.mutate_value(val);
+            self.set_var(target, new_val);
+            if self.entropy.is_multiple_of(10) {
+                buffer.warn("⚠ Reality frays...");
+                self.drain_sanity(SanityCost::RealityFrays);
+            }
+        }
+    }
+
+    fn purge(&mut self, name: &str, buffer: &mut Events) {
+        if let Some(fragments) = self.memory_fragments.remove(name) {
+            buffer.narrate(format!("{} fragments of '{}' dissolve", fragments.len(), name));
+        }
+    }
+
+    fn forget(&mut self, name: &str, buffer: &mut Events) {
+        if let Some(frame) = self.call_stack.last_mut() {
+            frame.remove(name);
+        } else {
+            self.global_env.remove(name);
+        }
+        buffer.narrate(format!("Forgotten: {}... but fragments remain", name));
+    }
+
+    fn infect(&mut self, name: &str, buffer: &mut Events) {
+        let infection = Infection {
+            source: name.to_string(),
+            virulence: 0.5 + (self.temporal_drift() * 0.5),
+            mutation_vector: 0,
         };
         
-        self.execution_depth -= 1;
-        result
+        self.infections.insert(name.to_string(), infection);
+        buffer.emit(Event::InfectionSpread { source: None, target: name.to_string() });
+        self.drain_sanity(SanityCost::Infect);
+        
+        self.spread_infection(name, buffer);
     }
-    
-        fn execute_normal_loop(&mut self, condition: Expr, body: Vec<Stmt>, buffer: &mut String) -> Result<Option<Value>, String> {
-    
-            let mut iterations = 0;
-    
-            loop {
-    
-                let cond_val = self.eval_expr(condition.clone(), buffer)?;
-    
-                if !self.is_truthy(&cond_val) { break; }
-    
-                if let Some(val) = self.execute(&body, buffer)? { return Ok(Some(val)); }
-    
-                iterations += 1;
-    
-                self.sanity -= 0.5;
-    
-                if iterations > 1000 {
-    
-                    self.log("\n⚠ Hastur, Hastur, Hastur!", buffer);
-    
-                    self.sanity -= 20.0;
-    
-                    break;
-    
-                }
-    
-            }
-    
-            Ok(None)
-    
+
+    fn whisper(&mut self, code: &str, buffer: &mut Events) -> Result<Option<Value>, String> {
+        self.whisper_count += 1;
+        if self.whisper_count > self.max_whispers {
+            buffer.warn("⚠ Whisper limit exceeded.");
+            self.whisper_count -= 1;
+            return Ok(None);
         }
-    
-        fn execute_non_euclidean_loop(&mut self, condition: Expr, body: Vec<Stmt>, buffer: &mut String) -> Result<Option<Value>, String> {
-    
-            self.log("⚠ Non-Euclidean loop: space folds upon itself", buffer);
-    
-            let paradox_iterations = ((self.pseudo_random() * 10.0) as i32).max(1);
-    
-            for _ in 0..paradox_iterations {
-    
-                let cond_val = self.eval_expr(condition.clone(), buffer)?;
-    
-                let should_continue = if self.sanity < 30.0 { self.pseudo_random() > 0.3 } else { self.is_truthy(&cond_val) };
-    
-                if !should_continue { break; }
-    
-                if let Some(val) = self.execute(&body, buffer)? { return Ok(Some(val)); }
-    
-                self.sanity -= 1.0;
-    
+        if code.len() > MAX_WHISPER_LENGTH {
+            buffer.warn("⚠ Whisper exceeds maximum length.");
+            self.whisper_count -= 1;
+            return Ok(None);
+        }
+        for pattern in &self.forbidden_patterns {
+            if code.to_lowercase().contains(&pattern.to_lowercase()) {
+                buffer.warn(format!("⚠ Forbidden incantation '{}' detected.", pattern));
+                self.whisper_count -= 1;
+                return Ok(None);
             }
-    
-            self.log(&format!("⚠ Rift loop completed {} iterations", paradox_iterations), buffer);
-    
-            Ok(None)
-    
         }
-    
-    fn spread_infection(&mut self, source: &str, buffer: &mut String) {
-        let keys: Vec<String> = self.call_stack.last().map(|f| f.keys().cloned().collect()).unwrap_or_else(|| self.global_env.keys().cloned().collect());
 
-        for name in keys {
-            if name != source && self.pseudo_random() > 0.7 {
-                if let Some(infection) = self.infections.get(source).cloned() {
-                    self.infections.insert(name.clone(), Infection {
-                        source: name.clone(),
-                        virulence: infection.virulence * 0.7,
-                        mutation_vector: 0,
-                    });
-                    self.log(&format!("  ↳ Infection spreads to '{}'", name), buffer);
+        buffer.narrate(format!("◈ Whisper manifests: {}", code));
+
+        let mut lexer = Lexer::new(code);
+        let tokens = lexer.tokenize();
+        let mut parser = Parser::with_policy(tokens, self.policy.as_ref());
+        self.generated_code.push(code.to_string());
+
+        match parser.parse_program() {
+            Ok(ast) => {
+                if ast.len() > MAX_WHISPER_STATEMENTS {
+                    buffer.warn("⚠ Whisper AST too complex.");
+                    self.whisper_count -= 1;
+                    return Ok(None);
                 }
+                self.drain_sanity(SanityCost::Whisper);
+                self.execute(&ast, buffer)
             }
+            Err(_) => {
+                buffer.warn("⚠ Whisper fails to manifest properly");
+                self.whisper_count -= 1;
+                Ok(None)
+            }
+        }
+    }
+
+    fn anchor(&mut self, buffer: &mut Events) {
+        self.reality_stable = true;
+        self.drain_sanity(SanityCost::Anchor);
+        if self.sanity > 100.0 {
+            self.sanity = 100.0;
         }
+        buffer.narrate("Reality temporarily stabilized");
     }
     
-    fn eval_expr(&mut self, expr: Expr, buffer: &mut String) -> Result<Value, String> {
-        match expr {
-            Expr::Number(n) => {
-                if self.sanity < 40.0 && !self.reality_stable {
-                    let drift = (self.temporal_drift() - 0.5) * 4.0;
-                    Ok(Value::Number(n + drift))
-                } else {
-                    Ok(Value::Number(n))
-                }
-            }
-            Expr::String(s) => Ok(Value::String(s)),
-            Expr::Boolean(b) => Ok(Value::Boolean(b)),
-            Expr::Identifier(name) => {
-                let val = self.get_var(&name)
-                    .ok_or_else(|| format!("Undefined: '{}'", name))?;
-                Ok(self.apply_infection_corruption(&name, val))
-            }
-            Expr::BinaryOp { left, op, right } => {
-                let l = self.eval_expr(*left, buffer)?;
-                let r = self.eval_expr(*right, buffer)?;
-                
-                let instability = 1.0 - (self.sanity / 100.0);
-                self.apply_binary_op(l, op, r, 0.0, instability, buffer)
-            }
-            Expr::Call { name, args } => {
-                let func = self.get_var(&name)
-                    .ok_or_else(|| format!("Unknown act: {}", name))?;
-                
-                match func {
-                    Value::Function { params, body } => {
-                        if params.len() != args.len() {
-                            return Err(format!("Arity mismatch"));
-                        }
-                        
-                        let mut frame = BTreeMap::new();
-                        for (param, arg_expr) in params.iter().zip(args.iter()) {
-                            let arg_val = self.eval_expr(arg_expr.clone(), buffer)?;
-                            frame.insert(param.clone(), arg_val);
+    fn execute_normal_loop(&mut self, condition: &Expr, body: &[Stmt], buffer: &mut Events) -> Result<Option<Value>, String> {
+        let mut iterations = 0;
+        loop {
+            let cond_val = self.eval_expr(condition, buffer)?;
+            if !self.is_truthy(&cond_val) { break; }
+            if let Some(val) = self.execute(body, buffer)? { return Ok(Some(val)); }
+            if self.loop_overflowed(&mut iterations, buffer) { break; }
+        }
+        Ok(None)
+    }
+
+    /// Charges one Hastur iteration; true once the loop has run too long and must stop.
+    fn loop_overflowed(&mut self, iterations: &mut u32, buffer: &mut Events) -> bool {
+        *iterations += 1;
+        self.drain_sanity(SanityCost::LoopIteration);
+        if *iterations > self.limits.max_loop_iterations {
+            buffer.warn("\n⚠ Hastur, Hastur, Hastur!");
+            self.drain_sanity(SanityCost::LoopOverflow);
+            return true;
+        }
+        false
+    }
+
+    fn execute_non_euclidean_loop(&mut self, condition: &Expr, body: &[Stmt], buffer: &mut Events) -> Result<Option<Value>, String> {
+        if self.lucid {
+            return self.execute_normal_loop(condition, body, buffer);
+        }
+        buffer.warn("⚠ Non-Euclidean loop: space folds upon itself");
+
+        let paradox_iterations = self.paradox_iterations();
+        for _ in 0..paradox_iterations {
+            let cond_val = self.eval_expr(condition, buffer)?;
+            if !self.rift_continues(&cond_val) { break; }
+            if let Some(val) = self.execute(body, buffer)? { return Ok(Some(val)); }
+            self.drain_sanity(SanityCost::RiftIteration);
+        }
+
+        buffer.warn(format!("⚠ Rift loop completed {} iterations", paradox_iterations));
+        Ok(None)
+    }
+
+    fn paradox_iterations(&mut self) -> i32 {
+        ((self.pseudo_random() * 10.0) as i32).max(1)
+    }
+
+    fn rift_continues(&mut self, cond_val: &Value) -> bool {
+        if self.haunted(SanityThreshold::Critical) { self.pseudo_random() > 0.3 } else { self.is_truthy(cond_val) }
+    }
+    
+    fn spread_infection(&mut self, source: &str, buffer: &mut Events) {
+        if self.lucid {
+            return;
+        }
+        let keys: Vec<String> = self.call_stack.last().map(|f| f.keys()).unwrap_or_else(|| self.own_globals().map(|(name, _)| name.clone()).collect());
+
+        for name in keys {
+            if name != source && self.pseudo_random() > 0.7 {
+                if let Some(infection) = self.infections.get(source).cloned() {
+                    self.infections.insert(name.clone(), Infection {
+                        source: name.clone(),
+                        virulence: infection.virulence * 0.7,
+                        mutation_vector: 0,
+                    });
+                    buffer.emit(Event::InfectionSpread { source: Some(source.to_string()), target: name });
+                }
+            }
+        }
+    }
+    
+    fn eval_expr(&mut self, expr: &Expr, buffer: &mut Events) -> Result<Value, String> {
+        match expr {
+            Expr::Number(n) => Ok(self.number(*n)),
+            Expr::String(s) => Ok(Value::String(s.clone())),
+            Expr::Boolean(b) => Ok(Value::Boolean(*b)),
+            Expr::Identifier(name, slot) => self.load_var(*slot, name),
+            Expr::BinaryOp { left, op, right } => {
+                let l = self.eval_expr(left, buffer)?;
+                let r = self.eval_expr(right, buffer)?;
+                self.binary(l, *op, r, buffer)
+            }
+            Expr::Call { name, args } => {
+                let act = match self.lookup(name) {
+                    Some(Value::Function { act, .. }) => act.clone(),
+                    Some(Value::Native(native)) => {
+                        let native = native.clone();
+                        if native.arity() != args.len() {
+                            return Err("Arity mismatch".to_string());
                         }
-                        
-                        self.call_stack.push(frame);
-                        let result = self.execute(&body, buffer)?;
-                        self.call_stack.pop();
-                        Ok(result.unwrap_or(Value::Null))
+                        let args = args.iter().map(|arg| self.eval_expr(arg, buffer)).collect::<Result<Vec<_>, _>>()?;
+                        return self.call_native(&native, args, buffer);
                     }
-                    _ => Err(format!("{} is not callable", name)),
+                    Some(_) => return Err(format!("{} is not callable", name)),
+                    None => return Err(format!("Unknown act: {}", name)),
+                };
+                if act.arity != args.len() {
+                    return Err("Arity mismatch".to_string());
                 }
+                
+                let mut frame = Scope::new(act.locals.clone());
+                for (param, arg_expr) in act.params().iter().zip(args) {
+                    let arg_val = self.eval_expr(arg_expr, buffer)?;
+                    frame.store(Slot::Dynamic, param, arg_val);
+                }
+                
+                let depth = self.enter_call()?;
+                self.call_stack.push(frame);
+                let result = self.execute(&act.body, buffer);
+                self.call_stack.pop();
+                self.leave_call(depth);
+                Ok(result?.unwrap_or(Value::Null))
             }
             Expr::Rewrite { target } => {
-                let mut val = self.eval_expr(*target, buffer)?;
-                val = self.mutate_value(val);
-                Ok(val)
+                let val = self.eval_expr(target, buffer)?;
+                Ok(self.mutate_value(val))
             }
             Expr::Superpose(exprs) => {
                 let mut values = Vec::new();
                 for e in exprs {
-                    values.push(Box::new(self.eval_expr(e, buffer)?));
+                    values.push(self.eval_expr(e, buffer)?);
                 }
-                self.log(&format!("⟨ψ| Superposition of {} states", values.len()), buffer);
-                Ok(Value::Quantum(Box::new(QuantumState::Superposition(values))))
+                Ok(self.superpose(values, buffer))
             }
             Expr::Collapse(expr) => {
-                let val = self.eval_expr(*expr, buffer)?;
-                match val {
-                    Value::Quantum(qs) => match *qs {
-                        QuantumState::Superposition(ref vals) => {
-                            if vals.is_empty() {
-                                return Err("Collapse error: Superposition is empty".to_string());
-                            }
-                            let idx = (self.pseudo_random() * vals.len() as f64) as usize;
-                            let collapsed = vals[idx].clone();
-                            self.log(&format!("|ψ⟩ Collapsed to: {}", collapsed), buffer);
-                            Ok(*collapsed)
-                        }
-                        _ => Ok(Value::Quantum(qs)),
-                    },
-                    v => Ok(v),
-                }
+                let val = self.eval_expr(expr, buffer)?;
+                self.collapse(val, buffer)
             }
-            Expr::Manifest(name) => {
-                if let Some(fragments) = self.memory_fragments.get_mut(&name) {
-                    if let Some(val) = fragments.pop_back() {
-                        self.log(&format!("◈ Manifesting '{}'", name), buffer);
-                        return Ok(val);
-                    }
-                }
-                Ok(Value::Null)
+            Expr::Manifest(name) => Ok(self.manifest(name, buffer)),
+            Expr::Peek(name) => Ok(self.peek(name)),
+            Expr::Oldest(name) => Ok(self.oldest(name)),
+            Expr::Unearth(name) => Ok(self.unearth(name, buffer)),
+            Expr::Fragments(name) => Ok(self.fragment_count(name)),
+            Expr::Entangle(var1, var2) => Ok(self.entangle(var1, var2, buffer)),
+            Expr::Rift(expr) => {
+                self.rift_expression(buffer);
+                self.eval_expr(expr, buffer)
+            }
+            Expr::Before(name, steps) => {
+                let steps = self.eval_expr(steps, buffer)?;
+                self.before(name, steps, buffer)
             }
-            Expr::Entangle(var1, var2) => {
-                self.log(&format!("⟨⟩ Entangling '{}' with '{}'", var1, var2), buffer);
+            Expr::During(name, timestamp) => {
+                let timestamp = self.eval_expr(timestamp, buffer)?;
+                self.during(name, timestamp, buffer)
+            }
+            Expr::Echoes(name) => Ok(self.echoes(name, buffer)),
+        }
+    }
 
-                if let Some(val2) = self.get_var(&var2) {
-                    self.set_var(var1.clone(), Value::Quantum(Box::new(QuantumState::Entangled(var2.clone()))));
-                    Ok(val2)
-                } else {
-                    Ok(Value::Null)
+    /// A numeric literal, which drifts once reality is unanchored and sanity is low.
+    fn number(&mut self, n: f64) -> Value {
+        if self.haunted(SanityThreshold::Drift) && !self.reality_stable {
+            let drift = (self.temporal_drift() - 0.5) * 4.0;
+            Value::Number(n + drift)
+        } else {
+            Value::Number(n)
+        }
+    }
+
+    fn load_var(&mut self, slot: Slot, name: &str) -> Result<Value, String> {
+        let val = self.lookup_slot(slot, name).cloned()
+            .ok_or_else(|| format!("Undefined: '{}'", name))?;
+        Ok(self.apply_infection_corruption(name, val))
+    }
+
+    fn binary(&mut self, l: Value, op: BinaryOperator, r: Value, buffer: &mut Events) -> Result<Value, String> {
+        let instability = if self.lucid { 0.0 } else { 1.0 - (self.sanity / 100.0) };
+        self.apply_binary_op(l, op, r, 0.0, instability, buffer)
+    }
+
+    /// An `assert` on a comparison, which reports both sides when it fails.
+    fn assert_binary(&mut self, text: &str, l: Value, op: BinaryOperator, r: Value, buffer: &mut Events) -> Result<(), String> {
+        let result = self.binary(l.clone(), op, r.clone(), buffer)?;
+        if self.is_truthy(&result) {
+            Ok(())
+        } else {
+            Err(format!("Assertion failed: {} (left: {}, right: {})", text, shown(&l), shown(&r)))
+        }
+    }
+
+    fn assert_value(&mut self, text: &str, val: Value) -> Result<(), String> {
+        if self.is_truthy(&val) {
+            Ok(())
+        } else {
+            Err(format!("Assertion failed: {} (was {})", text, shown(&val)))
+        }
+    }
+
+    fn superpose(&mut self, values: Vec<Value>, buffer: &mut Events) -> Value {
+        buffer.narrate(format!("⟨ψ| Superposition of {} states", values.len()));
+        Value::Quantum(Box::new(QuantumState::Superposition(values)))
+    }
+
+    fn collapse(&mut self, val: Value, buffer: &mut Events) -> Result<Value, String> {
+        match val {
+            Value::Quantum(qs) => match *qs {
+                QuantumState::Superposition(ref vals) => {
+                    if vals.is_empty() {
+                        return Err("Collapse error: Superposition is empty".to_string());
+                    }
+                    let idx = self.choose(vals.len());
+                    let collapsed = vals[idx].clone();
+                    buffer.emit(Event::Collapse { value: collapsed.to_string() });
+                    Ok(collapsed)
                 }
+                _ => Ok(Value::Quantum(qs)),
+            },
+            v => Ok(v),
+        }
+    }
+
+    fn manifest(&mut self, name: &str, buffer: &mut Events) -> Value {
+        if let Some(fragments) = self.memory_fragments.get_mut(name) {
+            if let Some(val) = fragments.pop_back() {
+                buffer.narrate(format!("◈ Manifesting '{}'", name));
+                return val;
             }
-            Expr::Rift(expr) => {
-                self.log("⚠ Non-Euclidean expression", buffer);
-                self.sanity -= 2.0;
-                self.eval_expr(*expr, buffer)
+        }
+        Value::Null
+    }
+
+    fn peek(&self, name: &str) -> Value {
+        self.memory_fragments
+            .get(name)
+            .and_then(|fragments| fragments.back().cloned())
+            .unwrap_or(Value::Null)
+    }
+
+    /// The front of `name`'s fragments, left where it is; `unearth` takes it away.
+    fn oldest(&self, name: &str) -> Value {
+        self.memory_fragments
+            .get(name)
+            .and_then(|fragments| fragments.front().cloned())
+            .unwrap_or(Value::Null)
+    }
+
+    fn unearth(&mut self, name: &str, buffer: &mut Events) -> Value {
+        if let Some(fragments) = self.memory_fragments.get_mut(name) {
+            if let Some(val) = fragments.pop_front() {
+                buffer.narrate(format!("◈ Unearthing the oldest '{}'", name));
+                return val;
             }
         }
+        Value::Null
+    }
+
+    fn fragment_count(&self, name: &str) -> Value {
+        let count = self.memory_fragments.get(name).map_or(0, VecDeque::len);
+        Value::Number(count as f64)
+    }
+
+    fn entangle(&mut self, var1: &str, var2: &str, buffer: &mut Events) -> Value {
+        buffer.narrate(format!("⟨⟩ Entangling '{}' with '{}'", var1, var2));
+
+        if let Some(val2) = self.get_var(var2) {
+            self.set_var(var1, Value::Quantum(Box::new(QuantumState::Entangled(var2.to_string()))));
+            val2
+        } else {
+            Value::Null
+        }
+    }
+
+    fn rift_expression(&mut self, buffer: &mut Events) {
+        if !self.lucid {
+            buffer.warn("⚠ Non-Euclidean expression");
+        }
+        self.drain_sanity(SanityCost::RiftExpression);
+    }
+
+    /// What `name` was set to `steps` assignments before its latest one, so `before(x, 0)`
+    /// is the latest. Steps count assignments, not entropy ticks; `during` reads by tick.
+    fn before(&mut self, name: &str, steps: Value, buffer: &mut Events) -> Result<Value, String> {
+        let steps = self.timestamp(steps)?;
+        buffer.narrate(format!("⟲ Peering {} assignments into the past of '{}'", steps, name));
+        Ok(self
+            .temporal_echoes
+            .iter()
+            .rev()
+            .filter(|echo| echo.variable_name == name)
+            .nth(steps as usize)
+            .map_or(Value::Null, |echo| (*echo.ghost_value).clone()))
+    }
+
+    fn during(&mut self, name: &str, timestamp: Value, buffer: &mut Events) -> Result<Value, String> {
+        let timestamp = self.timestamp(timestamp)?;
+        buffer.narrate(format!("⟲ Peering into '{}' at entropy {}", name, timestamp));
+        Ok(self.echo_at(name, timestamp).unwrap_or(Value::Null))
+    }
+
+    /// Every remembered value of `name`, oldest first.
+    fn echoes(&mut self, name: &str, buffer: &mut Events) -> Value {
+        let history: Vec<Value> = self.temporal_echoes
+            .iter()
+            .filter(|echo| echo.variable_name == name)
+            .map(|echo| (*echo.ghost_value).clone())
+            .collect();
+        buffer.narrate(format!("⟲ '{}' echoes through {} past states", name, history.len()));
+        Value::History(history)
     }
     
-    fn apply_binary_op(&mut self, l: Value, op: BinaryOperator, r: Value, hash_influence: f64, instability: f64, buffer: &mut String) -> Result<Value, String> {
+    fn apply_binary_op(&mut self, l: Value, op: BinaryOperator, r: Value, hash_influence: f64, instability: f64, buffer: &mut Events) -> Result<Value, String> {
         match (l, r) {
             (Value::Number(a), Value::Number(b)) => {
                 let result = match op {
@@ -1325,8 +2340,8 @@ impl Interpreter {
                     }
                     BinaryOperator::Div => {
                         if b == 0.0 {
-                            self.log("⚠ Division by zero", buffer);
-                            self.sanity -= 10.0;
+                            buffer.warn("⚠ Division by zero");
+                            self.drain_sanity(SanityCost::DivisionByZero);
                             f64::INFINITY
                         } else {
                             a / b
@@ -1361,13 +2376,18 @@ impl Interpreter {
     fn evaluate_condition(&mut self, val: &Value, drift: f64) -> bool {
         match val {
             Value::Boolean(b) => {
-                if self.sanity < 40.0 && self.pseudo_random() > 0.8 { !b } else { *b }
+                if self.haunted(SanityThreshold::Drift) && self.pseudo_random() > 0.8 {
+                    self.note(|draw| Effect::Flip { draw, from: *b });
+                    !b
+                } else {
+                    *b
+                }
             }
             Value::Number(n) => *n > (0.5 + drift * 0.3),
             Value::Quantum(qs) => match **qs {
                 QuantumState::Superposition(ref vals) => {
                     if vals.is_empty() { return false; }
-                    let idx = (self.pseudo_random() * vals.len() as f64) as usize;
+                    let idx = self.choose(vals.len());
                     self.is_truthy(&vals[idx])
                 }
                 _ => false,
@@ -1401,9 +2421,9 @@ impl Interpreter {
     }
 
     fn distort_output(&mut self, s: &str) -> String {
-        let distortions = vec![" ", "◈", "⚠", "⟨", "⟩", "↯"];
+        let distortions = [" ", "◈", "⚠", "⟨", "⟩", "↯"];
         let mut result = String::new();
-        for (_i, c) in s.chars().enumerate() {
+        for c in s.chars() {
             if self.pseudo_random() > 0.7 {
                 let idx = (self.pseudo_random() * distortions.len() as f64) as usize;
                 result.push_str(distortions[idx]);
@@ -1422,9 +2442,228 @@ impl Interpreter {
 // Hook removed for stability
 // pub fn init_hooks() {}
 
+/// Which execution engine runs parsed programs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    /// Compile to bytecode and run on the stack VM.
+    Bytecode,
+    /// Walk the AST directly; the reference semantics.
+    TreeWalk,
+}
+
+impl Engine {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "bytecode" => Ok(Engine::Bytecode),
+            "tree" => Ok(Engine::TreeWalk),
+            _ => Err(format!("Unknown engine '{}'", name)),
+        }
+    }
+}
+
+/// Where a sliced run started by `start` stands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunStatus {
+    Idle,
+    Running,
+    /// Stopped by the debugger; `resume` lets it run again.
+    Paused,
+    Finished,
+    Errored,
+    /// Ended by spending its fuel, not by anything the program did wrong.
+    OutOfFuel,
+}
+
+impl RunStatus {
+    fn name(self) -> &'static str {
+        match self {
+            RunStatus::Idle => "idle",
+            RunStatus::Running => "running",
+            RunStatus::Paused => "paused",
+            RunStatus::Finished => "finished",
+            RunStatus::Errored => "errored",
+            RunStatus::OutOfFuel => "out_of_fuel",
+        }
+    }
+}
+
+/// `source` in canonical layout: four spaces per level, one statement a line, spaced
+/// operators and `->`. Comments stay where they were. Fails if `source` does not parse.
+#[wasm_bindgen]
+pub fn format_code(source: &str) -> Result<String, String> {
+    printer::format(source)
+}
+
+/// Every token and comment in `source`, classified for syntax highlighting, as a JSON
+/// array of `{"kind", "line", "column", "start", "end"}`. `kind` is one of "keyword",
+/// "horror" (Hastur, Cassilda, Carcosa), "quantum" (superpose, collapse, entangle),
+/// "identifier", "number", "string", "constant" (yellow, tattered, pallid), "operator",
+/// "punctuation", "comment" or "unknown"; `start` and `end` are UTF-16 offsets. Never
+/// fails, however incomplete the source.
+#[wasm_bindgen]
+pub fn highlight(source: &str) -> String {
+    highlight::classify(source)
+}
+
+/// The interpreter as embedders see it, from Rust or, through wasm-bindgen, from
+/// JavaScript: running and debugging programs, registering host acts, and taking
+/// and restoring snapshots all go through here.
 #[wasm_bindgen]
 pub struct YellowWebInterpreter {
     interpreter: Interpreter,
+    engine: Engine,
+    machine: Option<vm::Machine>,
+    status: RunStatus,
+    /// What the latest run has reported so far.
+    events: Events,
+    debugger: Debugger,
+    /// Whether runs are recorded into `trace`.
+    recording: bool,
+    /// The trace of the latest recorded run that finished.
+    trace: Option<String>,
+    /// Leaves out the parsing banner and the closing summary.
+    quiet: bool,
+}
+
+impl Default for YellowWebInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl YellowWebInterpreter {
+    /// What `check` finds, for Rust callers such as the `yellow` binary.
+    pub fn diagnostics(&self, source: &str) -> Vec<Diagnostic> {
+        self.interpreter.check(source)
+    }
+
+    /// Lets Yellow code call `act` as `name` with `arity` arguments, like a built-in act.
+    /// An `Err` from `act` is a runtime horror. Registering `name` again replaces the act.
+    pub fn register_act(
+        &mut self,
+        name: &str,
+        arity: usize,
+        act: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Result<(), String> {
+        self.interpreter.register_act(name, arity, Rc::new(act))
+    }
+
+    /// Parses and resolves `source`, printing the banner. `None` once a parse error is reported.
+    fn prepare(&mut self, source: &str, buffer: &mut Events) -> Option<Vec<Stmt>> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+
+        let mut parser = Parser::with_policy(tokens, self.interpreter.policy.as_ref());
+        match parser.parse_program() {
+            Ok(mut ast) => {
+                if !self.quiet {
+                    buffer.narrate(format!("Parsing complete. Sanity: {:.1}%", parser.sanity));
+                }
+                // What the resolver finds is for `check` and the language server; runs stay as they were.
+                resolver::resolve(&mut ast, &self.interpreter.global_env);
+                if !self.quiet {
+                    buffer.narrate("\n╔════════════════════════════════════════╗");
+                    buffer.narrate("║  Beginning execution...                ║");
+                    buffer.narrate("╚════════════════════════════════════════╝\n");
+                }
+                Some(ast)
+            }
+            Err(e) => {
+                buffer.emit(Event::Error { phase: Phase::Parse, message: e });
+                buffer.narrate(format!("Sanity remaining: {:.1}%", parser.sanity));
+                None
+            }
+        }
+    }
+
+    /// Prints the closing summary, or the horror that ended the run.
+    fn conclude(&mut self, result: Result<Option<Value>, String>, buffer: &mut Events) {
+        buffer.finish();
+        self.status = match result {
+            Ok(_) => RunStatus::Finished,
+            Err(_) if self.interpreter.out_of_fuel => RunStatus::OutOfFuel,
+            Err(_) => RunStatus::Errored,
+        };
+        match result {
+            Ok(_) if self.quiet => {}
+            Ok(_) => {
+                buffer.narrate("\n╔════════════════════════════════════════╗");
+                buffer.narrate("║  Program completed                     ║");
+                buffer.narrate(format!("║  Final sanity: {:<24.1}%║", self.interpreter.sanity));
+                buffer.narrate(format!("║  Infections: {:<26} ║", self.interpreter.infections.len()));
+                buffer.narrate(format!("║  Temporal echoes: {:<20} ║", self.interpreter.temporal_echoes.len()));
+                buffer.narrate(format!("║  Memory fragments: {:<19} ║", self.interpreter.memory_fragments.len()));
+                buffer.narrate(format!("║  Phantom variables: {:<18} ║", self.interpreter.phantom_variables.len()));
+                buffer.narrate(format!("║  Generated code blocks: {:<14} ║", self.interpreter.generated_code.len()));
+                buffer.narrate("╚════════════════════════════════════════╝");
+
+                if self.interpreter.below(SanityThreshold::Critical) {
+                    buffer.warn("\n⚠⚠⚠ WARNING ⚠⚠⚠");
+                    buffer.narrate("Critical sanity levels detected.");
+                    buffer.narrate("Reality may be permanently compromised.");
+                }
+            }
+            Err(e) if self.interpreter.out_of_fuel => buffer.emit(Event::Error { phase: Phase::Fuel, message: e }),
+            Err(e) => {
+                buffer.emit(Event::Error { phase: Phase::Runtime, message: e });
+                buffer.narrate("The code consumes itself...");
+            }
+        }
+    }
+
+    /// Drops a sliced run that never finished, taking its frames off the interpreter.
+    fn abandon(&mut self) {
+        if let Some(mut machine) = self.machine.take() {
+            machine.unwind(&mut self.interpreter);
+        }
+        self.status = RunStatus::Idle;
+        self.events = Events::default();
+        self.debugger.reset(false);
+        self.interpreter.recorder = None;
+    }
+
+    /// Parses, runs and concludes `source` in one go.
+    fn execute(&mut self, source: &str, buffer: &mut Events) {
+        self.interpreter.fuel_used = 0;
+        self.interpreter.out_of_fuel = false;
+        let Some(ast) = self.prepare(source, buffer) else {
+            self.status = RunStatus::Errored;
+            return;
+        };
+        // A replay is already recording, to compare against its trace.
+        if self.recording && self.interpreter.recorder.is_none() {
+            self.interpreter.start_recording(source);
+        }
+        let result = match self.engine {
+            Engine::Bytecode => {
+                let chunk = Rc::new(compiler::compile(&ast));
+                self.interpreter.run_compiled(chunk, buffer)
+            }
+            Engine::TreeWalk => self.interpreter.execute(&ast, buffer),
+        };
+        self.conclude(result, buffer);
+    }
+
+    fn begin(&mut self, source: &str, stop_at_first: bool) {
+        self.abandon();
+        self.trace = None;
+        self.interpreter.fuel_used = 0;
+        self.interpreter.out_of_fuel = false;
+        self.debugger.reset(stop_at_first);
+        let mut events = Events::default();
+        self.status = match self.prepare(source, &mut events) {
+            Some(ast) => {
+                if self.recording {
+                    self.interpreter.start_recording(source);
+                }
+                let chunk = Rc::new(compiler::compile(&ast));
+                self.machine = Some(vm::Machine::new(&self.interpreter, chunk));
+                RunStatus::Running
+            }
+            None => RunStatus::Errored,
+        };
+        self.events = events;
+    }
 }
 
 #[wasm_bindgen]
@@ -1433,54 +2672,368 @@ impl YellowWebInterpreter {
     pub fn new() -> Self {
         YellowWebInterpreter {
             interpreter: Interpreter::new(),
+            engine: Engine::TreeWalk,
+            machine: None,
+            status: RunStatus::Idle,
+            events: Events::default(),
+            debugger: Debugger::default(),
+            recording: false,
+            trace: None,
+            quiet: false,
         }
     }
 
+    /// Seeds the RNG so a haunted run can be reproduced exactly.
+    pub fn with_seed(seed: u64) -> Self {
+        YellowWebInterpreter {
+            interpreter: Interpreter::with_seed(seed),
+            engine: Engine::TreeWalk,
+            machine: None,
+            status: RunStatus::Idle,
+            events: Events::default(),
+            debugger: Debugger::default(),
+            recording: false,
+            trace: None,
+            quiet: false,
+        }
+    }
+
+    /// Current RNG state; passing it to `with_seed` resumes the same sequence of hallucinations.
+    /// While a trace is being replayed it is instead how many recorded draws have been served.
+    pub fn get_rng_state(&self) -> u64 {
+        self.interpreter.rng.state()
+    }
+
     pub fn run_code(&mut self, source: &str) -> String {
-        let mut output_buffer = String::new();
-        
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.tokenize();
-        
-        let mut parser = Parser::new(tokens);
-        match parser.parse_program() {
-            Ok(ast) => {
-                self.interpreter.log(&format!("Parsing complete. Sanity: {:.1}%", parser.sanity), &mut output_buffer);
-                self.interpreter.log("\n╔════════════════════════════════════════╗", &mut output_buffer);
-                self.interpreter.log("║  Beginning execution...                ║", &mut output_buffer);
-                self.interpreter.log("╚════════════════════════════════════════╝\n", &mut output_buffer);
-                
-                match self.interpreter.execute(&ast, &mut output_buffer) {
-                    Ok(_) => {
-                        self.interpreter.log("\n╔════════════════════════════════════════╗", &mut output_buffer);
-                        self.interpreter.log("║  Program completed                     ║", &mut output_buffer);
-                        self.interpreter.log(&format!("║  Final sanity: {:<24.1}%║", self.interpreter.sanity), &mut output_buffer);
-                        self.interpreter.log(&format!("║  Infections: {:<26} ║", self.interpreter.infections.len()), &mut output_buffer);
-                        self.interpreter.log(&format!("║  Temporal echoes: {:<20} ║", self.interpreter.temporal_echoes.len()), &mut output_buffer);
-                        self.interpreter.log(&format!("║  Memory fragments: {:<19} ║", self.interpreter.memory_fragments.len()), &mut output_buffer);
-                        self.interpreter.log(&format!("║  Phantom variables: {:<18} ║", self.interpreter.phantom_variables.len()), &mut output_buffer);
-                        self.interpreter.log(&format!("║  Generated code blocks: {:<14} ║", self.interpreter.generated_code.len()), &mut output_buffer);
-                        self.interpreter.log("╚════════════════════════════════════════╝", &mut output_buffer);
-                        
-                        if self.interpreter.sanity < 30.0 {
-                            self.interpreter.log("\n⚠⚠⚠ WARNING ⚠⚠⚠", &mut output_buffer);
-                            self.interpreter.log("Critical sanity levels detected.", &mut output_buffer);
-                            self.interpreter.log("Reality may be permanently compromised.", &mut output_buffer);
-                        }
-                    }
-                    Err(e) => {
-                        self.interpreter.log(&format!("\n⚠ Runtime horror: {}", e), &mut output_buffer);
-                        self.interpreter.log("The code consumes itself...", &mut output_buffer);
-                    }
+        self.abandon();
+        let mut events = Events::default();
+        self.execute(source, &mut events);
+        self.events = events;
+        let transcript = self.events.render_text();
+        self.trace = self.interpreter.finish_recording(&transcript);
+        transcript
+    }
+
+    /// Begins a run that `step` advances a slice at a time, so a long program never blocks
+    /// the page. Sliced runs always use the bytecode engine; the tree-walker cannot pause.
+    /// Breakpoints are honoured.
+    pub fn start(&mut self, source: &str) {
+        self.begin(source, false);
+    }
+
+    /// Like `start`, but pauses before the first statement.
+    pub fn debug(&mut self, source: &str) {
+        self.begin(source, true);
+    }
+
+    /// Executes up to `instructions` more instructions of the started run and returns its
+    /// status: "idle", "running", "paused", "finished", "errored" or "out_of_fuel". A paused
+    /// run stays put until `resume`.
+    pub fn step(&mut self, instructions: u32) -> String {
+        if self.status == RunStatus::Paused {
+            return self.get_status();
+        }
+        if let Some(machine) = self.machine.as_mut() {
+            let mut events = std::mem::take(&mut self.events);
+            match machine.advance(&mut self.interpreter, &mut events, instructions as usize, Some(&mut self.debugger)) {
+                Ok(vm::Progress::Suspended) => {}
+                Ok(vm::Progress::Paused) => self.status = RunStatus::Paused,
+                Ok(vm::Progress::Finished(value)) => {
+                    self.machine = None;
+                    self.conclude(Ok(value), &mut events);
+                    self.trace = self.interpreter.finish_recording(&events.render_text());
+                }
+                Err(e) => {
+                    self.machine = None;
+                    self.conclude(Err(e), &mut events);
+                    self.trace = self.interpreter.finish_recording(&events.render_text());
                 }
             }
-            Err(e) => {
-                self.interpreter.log(&format!("\n⚠ Parse error: {}", e), &mut output_buffer);
-                self.interpreter.log(&format!("Sanity remaining: {:.1}%", parser.sanity), &mut output_buffer);
-            }
+            self.events = events;
         }
+        self.get_status()
+    }
+
+    /// "finished", "errored" or "out_of_fuel" once any run is over, including `run_code` and `replay`.
+    pub fn get_status(&self) -> String {
+        self.status.name().to_string()
+    }
+
+    /// The latest run's transcript so far, as `run_code` would have printed it.
+    pub fn get_output(&self) -> String {
+        self.events.render_text()
+    }
+
+    /// Like `get_output`, coloured with ANSI escapes for a terminal.
+    pub fn get_output_ansi(&self) -> String {
+        self.events.render_ansi()
+    }
+
+    /// Every event of the latest run as a JSON array of objects tagged by `kind`:
+    /// "echo", "warning", "narration", "phantom_spawned", "infection_spread", "collapse",
+    /// "sanity_threshold", "error" or "murmur". Each carries its transcript line as `text`.
+    pub fn get_events(&self) -> String {
+        self.events.to_json()
+    }
+
+    /// Like `get_events`, but only the events recorded since the previous call.
+    pub fn take_events(&mut self) -> String {
+        self.events.take_json()
+    }
+
+    /// Engines: "tree" (default) or "bytecode", which is faster on long loops.
+    pub fn set_engine(&mut self, name: &str) -> Result<(), String> {
+        self.engine = Engine::parse(name)?;
+        Ok(())
+    }
+
+    /// Modes: "unbounded", "total", "per_variable" or "window" (in entropy ticks).
+    pub fn set_echo_retention(&mut self, mode: &str, limit: u32) -> Result<(), String> {
+        self.interpreter.echo_retention = EchoRetention::parse(mode, limit)?;
+        self.interpreter.trim_echoes();
+        Ok(())
+    }
+
+    pub fn set_fragment_capacity(&mut self, capacity: u32) {
+        self.interpreter.fragment_capacity = capacity as usize;
+        self.interpreter.trim_fragments();
+    }
+
+    /// Presets: "gentle", "standard" or "nightmare".
+    pub fn set_sanity_policy(&mut self, name: &str) -> Result<(), String> {
+        self.interpreter.policy = sanity_policy(name)?.into();
+        Ok(())
+    }
+
+    /// Quiet runs leave out the parsing banner and the closing summary, for consoles that
+    /// run one line at a time.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Lucid mode disables drift, flips, distortion, phantoms, echo bleed-through and the
+    /// warnings of rifts. Sanity is still counted, and running out of it still ends the run.
+    pub fn set_lucid(&mut self, lucid: bool) {
+        self.interpreter.lucid = lucid;
+    }
+
+    pub fn is_lucid(&self) -> bool {
+        self.interpreter.lucid
+    }
+
+    /// Caps the statements each run may execute; `None` removes the cap.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.interpreter.limits.fuel = fuel;
+    }
+
+    /// Statements executed by the last run.
+    pub fn get_fuel_used(&self) -> u64 {
+        self.interpreter.fuel_used
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: u32) {
+        self.interpreter.limits.max_call_depth = depth as usize;
+    }
+
+    pub fn set_max_nesting(&mut self, depth: u32) {
+        self.interpreter.limits.max_nesting = depth as usize;
+    }
+
+    pub fn set_max_loop_iterations(&mut self, iterations: u32) {
+        self.interpreter.limits.max_loop_iterations = iterations;
+    }
+
+    /// Globals and active frames as JSON: `{"globals": {...}, "frames": [{...}]}`, innermost
+    /// frame last. Numbers, strings, booleans and pallid map to JSON; acts and quantum
+    /// values are objects tagged by `kind`.
+    pub fn inspect_variables(&self) -> String {
+        self.interpreter.variables_json()
+    }
+
+    /// `[{"name", "source", "virulence", "mutation_vector"}]`.
+    pub fn inspect_infections(&self) -> String {
+        self.interpreter.infections_json()
+    }
+
+    /// `[{"timestamp", "name", "value", "stability"}]`, oldest first.
+    pub fn inspect_echoes(&self) -> String {
+        self.interpreter.echoes_json()
+    }
+
+    /// `{"name": [values, oldest first]}`.
+    pub fn inspect_fragments(&self) -> String {
+        self.interpreter.fragments_json()
+    }
+
+    pub fn inspect_phantoms(&self) -> String {
+        self.interpreter.phantoms_json()
+    }
+
+    /// Every whisper that took shape, in order.
+    pub fn inspect_generated_code(&self) -> String {
+        self.interpreter.generated_code_json()
+    }
+
+    /// All of the above in one object, with sanity, entropy and the lucid flag.
+    pub fn inspect(&self) -> String {
+        self.interpreter.state_json()
+    }
+
+    /// What is wrong with `source`, found without running it, as a JSON array of
+    /// `{"line", "column", "severity", "message"}` in source order. Acts, variables
+    /// and fragments this session already holds count as defined.
+    pub fn check(&self, source: &str) -> String {
+        checker::to_json(&self.interpreter.check(source))
+    }
+
+    /// Runs each top-level `trial` in `source` in a fresh session on this engine and policy, after
+    /// the rest of the program, as a JSON array of `{"name", "passed", "failure",
+    /// "sanity_consumed", "output"}`. This session is left as it was. Fails only if
+    /// `source` does not parse.
+    pub fn run_trials(&self, source: &str) -> Result<String, String> {
+        Ok(trial::to_json(&trial::run_trials_with(
+            source,
+            DEFAULT_SEED,
+            self.engine,
+            &self.interpreter.natives(),
+            &self.interpreter.policy,
+        )?))
+    }
+
+    /// `register_act` for JavaScript: `act` is called with numbers, strings, booleans and
+    /// `null` for pallid, and should return one of those. Anything it throws becomes a
+    /// runtime horror in the program.
+    pub fn register_js_act(&mut self, name: &str, arity: u32, act: host::JsAct) -> Result<(), String> {
+        self.interpreter.register_act(name, arity as usize, host::js_act(name, act))
+    }
 
-        output_buffer
+    /// Plain text for terminals, one entry a line: "sanity", "variables", "infections",
+    /// "echoes", "fragments" or "phantoms".
+    pub fn report(&self, topic: &str) -> Result<String, String> {
+        self.interpreter.report(topic)
+    }
+
+    pub fn get_entropy(&self) -> u64 {
+        self.interpreter.entropy
+    }
+
+    /// The whole session as JSON, for checkpoints or local storage. Only taken between runs.
+    pub fn snapshot(&self) -> Result<String, String> {
+        if matches!(self.status, RunStatus::Running | RunStatus::Paused) {
+            return Err("Reality is still shifting: finish or restart the run before taking a snapshot.".to_string());
+        }
+        Ok(self.interpreter.snapshot())
+    }
+
+    /// Rolls the session back to a `snapshot`. Any run in progress is abandoned either way;
+    /// a snapshot that does not read cleanly leaves the rest of the session untouched.
+    pub fn restore(&mut self, snapshot: &str) -> Result<(), String> {
+        self.abandon();
+        self.interpreter.restore(snapshot)
+    }
+
+    /// Records every run from now on: each random draw and the flips, drift, collapses and
+    /// phantoms it caused, with the source, policy, settings and session it started from.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// The latest recorded run as a JSON trace for `replay`, or `null` if it was not recorded.
+    pub fn get_trace(&self) -> String {
+        self.trace.clone().unwrap_or_else(|| "null".to_string())
+    }
+
+    /// Runs a trace again and returns its transcript. The recorded session, policy and
+    /// settings stand in for the current ones during the replay, so it reproduces the
+    /// recording exactly; afterwards the session, RNG included, is as the recorded run left
+    /// it and the settings are the host's again. A replay that strays from its recording ends with a warning.
+    pub fn replay(&mut self, trace: &str) -> Result<String, String> {
+        let trace = Trace::parse(trace)?;
+        self.abandon();
+        self.trace = None;
+        let host = self.interpreter.begin_replay(&trace)?;
+        let mut events = Events::default();
+        self.execute(&trace.source, &mut events);
+        if let Some(warning) = self.interpreter.end_replay(host, &trace, &events.render_text()) {
+            events.warn(warning);
+        }
+        self.events = events;
+        Ok(self.events.render_text())
+    }
+
+    /// Pauses `start` and `debug` runs before the first statement on `line`, counting from 1.
+    /// `run_code` never pauses.
+    pub fn set_breakpoint(&mut self, line: u32) {
+        self.debugger.set_breakpoint(line);
+    }
+
+    pub fn clear_breakpoint(&mut self, line: u32) {
+        self.debugger.clear_breakpoint(line);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+    }
+
+    /// The breakpoint lines as a JSON array, in order.
+    pub fn get_breakpoints(&self) -> String {
+        self.debugger.breakpoints_json()
+    }
+
+    /// Lets a paused run go on: "continue" to the next breakpoint, or step "into" the next
+    /// statement, "over" the current one, or "out" of the current act. `step` does the running.
+    pub fn resume(&mut self, mode: &str) -> Result<(), String> {
+        if self.status != RunStatus::Paused {
+            return Err("The run is not paused".to_string());
+        }
+        self.debugger.resume(mode)?;
+        self.status = RunStatus::Running;
+        Ok(())
+    }
+
+    /// Pauses a running program before its next statement.
+    pub fn pause(&mut self) {
+        if self.status == RunStatus::Running {
+            self.debugger.interrupt();
+        }
+    }
+
+    /// `{"line": n, "depth": frames}` for a paused run, otherwise `null`.
+    pub fn get_location(&self) -> String {
+        match self.debugger.paused() {
+            Some(at) if self.status == RunStatus::Paused => at.to_json(),
+            _ => "null".to_string(),
+        }
+    }
+
+    /// The paused run's frames, outermost first:
+    /// `[{"act": null or name, "line": n, "variables": {...}}]`.
+    pub fn get_call_stack(&self) -> String {
+        match &self.machine {
+            Some(machine) => debugger::call_stack_json(machine, &self.interpreter),
+            None => "[]".to_string(),
+        }
+    }
+
+    /// Watches `expression`, re-evaluated by every `get_watches`. False if it was already watched.
+    pub fn add_watch(&mut self, expression: &str) -> Result<bool, String> {
+        self.debugger.add_watch(expression)
+    }
+
+    pub fn remove_watch(&mut self, expression: &str) {
+        self.debugger.remove_watch(expression);
+    }
+
+    /// Every watch evaluated in the paused frame:
+    /// `[{"expression": ..., "value": ...}]`, with `"error"` in place of `"value"` when it fails.
+    pub fn get_watches(&mut self) -> String {
+        self.debugger.watches_json(&mut self.interpreter)
+    }
+
+    /// Evaluates `expression` in the paused frame (or the globals between runs) as JSON.
+    /// Whatever it does to sanity, echoes or the RNG is undone afterwards.
+    pub fn evaluate(&mut self, expression: &str) -> Result<String, String> {
+        self.interpreter.evaluate_watch(expression).map(|val| inspect::value(&val))
     }
 
     pub fn get_sanity(&self) -> f64 {
@@ -1492,3 +3045,191 @@ impl YellowWebInterpreter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripted(draws: Vec<f64>, sanity: f64) -> Interpreter {
+        let mut interp = Interpreter::with_rng(Box::new(ScriptedRng::new(draws)));
+        interp.sanity = sanity;
+        interp
+    }
+
+    fn number(val: Value) -> f64 {
+        match val {
+            Value::Number(n) => n,
+            val => panic!("expected a number, got {}", val),
+        }
+    }
+
+    #[test]
+    fn conditions_flip_on_high_draws_below_drift() {
+        let mut interp = scripted(vec![0.9, 0.1, 0.85], 30.0);
+        assert!(!interp.take_branch(&Value::Boolean(true)));
+        assert!(interp.take_branch(&Value::Boolean(true)));
+        assert!(interp.take_branch(&Value::Boolean(false)));
+        assert_eq!(interp.rng.state(), 3);
+    }
+
+    #[test]
+    fn conditions_hold_above_drift_and_when_lucid() {
+        let mut interp = scripted(vec![0.9], 60.0);
+        assert!(interp.take_branch(&Value::Boolean(true)));
+        interp.sanity = 30.0;
+        interp.lucid = true;
+        assert!(interp.take_branch(&Value::Boolean(true)));
+        assert_eq!(interp.rng.state(), 0);
+    }
+
+    #[test]
+    fn numbers_drift_with_entropy_once_unanchored() {
+        let mut interp = scripted(vec![], 20.0);
+        assert_eq!(number(interp.number(3.0)), 3.0);
+        interp.reality_stable = false;
+        interp.entropy = 100;
+        let drift = 1f64.tanh() * 0.8;
+        assert_eq!(number(interp.number(3.0)), 3.0 + (drift - 0.5) * 4.0);
+        assert_eq!(number(interp.mutate_value(Value::Number(3.0))), 3.0 + drift * 15.0 - 7.5);
+        interp.lucid = true;
+        assert_eq!(number(interp.mutate_value(Value::Number(3.0))), -4.5);
+    }
+
+    #[test]
+    fn acts_compile_once() {
+        let tokens = Lexer::new("act twice(n) { Carcosa n * 2; }").tokenize();
+        let ast = Parser::with_policy(tokens, &StandardPolicy).parse_program().unwrap();
+        let StmtKind::Act { act, .. } = &ast[0].kind else { panic!("expected an act") };
+        assert!(Rc::ptr_eq(&act.chunk(), &act.chunk()));
+    }
+
+    #[test]
+    fn spent_fuel_is_its_own_outcome() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.set_fuel(Some(3));
+        let output = interpreter.run_code("mask i -> 0;\nHastur (i < 10) {\n    mask i -> i + 1;\n}");
+        assert_eq!(interpreter.get_status(), "out_of_fuel");
+        assert!(output.contains("\n⚠ Out of fuel: the budget of 3 statements is spent.\n"), "{}", output);
+        assert!(!output.contains("Runtime horror"), "{}", output);
+        interpreter.set_fuel(None);
+        interpreter.run_code("mask i -> 0;");
+        assert_eq!(interpreter.get_status(), "finished");
+    }
+
+    /// Runs `source` on each engine after `limit`, giving back its status and transcript.
+    fn limited(limit: impl Fn(&mut YellowWebInterpreter), source: &str) -> Vec<(String, String)> {
+        ["bytecode", "tree"]
+            .into_iter()
+            .map(|engine| {
+                let mut interpreter = YellowWebInterpreter::new();
+                interpreter.set_engine(engine).unwrap();
+                limit(&mut interpreter);
+                let output = interpreter.run_code(source);
+                (interpreter.get_status(), output)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fuel_runs_out_alike_on_both_engines_and_in_slices() {
+        let source = "mask i -> 0;\nHastur (i < 10) {\n    mask i -> i + 1;\n}";
+        let runs = limited(|interpreter| interpreter.set_fuel(Some(5)), source);
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, "out_of_fuel");
+
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.set_fuel(Some(5));
+        interpreter.start(source);
+        while interpreter.step(1) == "running" {}
+        assert_eq!(interpreter.get_status(), "out_of_fuel");
+        assert_eq!(interpreter.get_fuel_used(), 5);
+        assert_eq!(interpreter.get_output(), runs[0].1);
+    }
+
+    #[test]
+    fn deep_calls_and_nesting_are_horrors() {
+        let runs = limited(
+            |interpreter| interpreter.set_max_call_depth(5),
+            "act down(n) {\n    Carcosa down(n + 1);\n}\necho(down(0));",
+        );
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, "errored");
+        assert!(runs[0].1.contains("\n⚠ Runtime horror: The act devours itself: Maximum call depth (5) exceeded.\n"));
+
+        let runs = limited(
+            |interpreter| interpreter.set_max_nesting(2),
+            "scene {\n    scene {\n        scene {\n            echo(1);\n        }\n    }\n}",
+        );
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, "errored");
+        assert!(runs[0].1.contains("\n⚠ Runtime horror: Reality fragmented: Maximum nesting depth (2) exceeded.\n"));
+    }
+
+    #[test]
+    fn endless_loops_are_broken_off() {
+        let source = "mask i -> 0;\nHastur (yellow) {\n    mask i -> i + 1;\n}\necho(i);";
+        let runs = limited(|interpreter| interpreter.set_max_loop_iterations(4), source);
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, "finished");
+        // The iteration past the limit runs before the loop is broken off.
+        assert!(runs[0].1.contains("⚠ Hastur, Hastur, Hastur!\n5\n"), "{}", runs[0].1);
+    }
+
+    #[test]
+    fn lucid_runs_still_end_when_sanity_runs_out() {
+        let source = "mask i -> 0;\nHastur (i < 300) {\n    mask i -> i + 1;\n}\necho(i);";
+        for engine in ["bytecode", "tree"] {
+            let mut interpreter = YellowWebInterpreter::new();
+            interpreter.set_engine(engine).unwrap();
+            interpreter.set_lucid(true);
+            let output = interpreter.run_code(source);
+            assert!(output.contains("[THE YELLOW SIGN HAS BEEN REVEALED]"), "{}", output);
+            assert!(!output.contains("\n300\n"), "{}", output);
+        }
+    }
+
+    #[test]
+    fn echoes_list_the_past_in_order_without_drawing() {
+        let mut interp = scripted(vec![], 100.0);
+        for val in [Value::Number(1.0), Value::String("ash".to_string()), Value::Number(3.0)] {
+            interp.set_var("a", val);
+        }
+        let history = interp.echoes("a", &mut Events::default());
+        assert_eq!(history.to_string(), "[1, \"ash\", 3]");
+        assert_eq!(interp.rng.state(), 0);
+    }
+
+    #[test]
+    fn seed_zero_is_a_seed_of_its_own() {
+        let source = "echo(random());\necho(collapse(superpose(1, 2, 3, 4, 5, 6, 7, 8)));\n";
+        let mut zero = YellowWebInterpreter::with_seed(0);
+        let mut default = YellowWebInterpreter::with_seed(DEFAULT_SEED);
+        assert_ne!(zero.get_rng_state(), default.get_rng_state());
+        assert_ne!(zero.run_code(source), default.run_code(source));
+        assert_eq!(YellowWebInterpreter::with_seed(0).run_code(source), YellowWebInterpreter::with_seed(0).run_code(source));
+    }
+
+    #[test]
+    fn trials_cost_what_the_session_policy_says() {
+        let source = "trial \"drift\" {\n    mask a -> superpose(1, 2);\n    echo(collapse(a));\n}\n";
+        let consumed = |policy: &str| {
+            let mut interpreter = YellowWebInterpreter::new();
+            interpreter.set_sanity_policy(policy).unwrap();
+            let outcomes = json::parse(&interpreter.run_trials(source).unwrap()).unwrap();
+            outcomes.as_array().unwrap()[0].get("sanity_consumed").and_then(json::Json::as_real).unwrap()
+        };
+        assert!(consumed("standard") > 0.0);
+        assert_eq!(consumed("nightmare"), consumed("standard") * 2.0);
+    }
+
+    #[test]
+    fn scripted_state_is_a_position() {
+        let mut interp = scripted(vec![0.25, 0.75], 100.0);
+        assert_eq!(interp.pseudo_random(), 0.25);
+        let state = interp.rng.state();
+        assert_eq!(interp.pseudo_random(), 0.75);
+        assert_eq!(interp.pseudo_random(), 0.25);
+        interp.rng.restore(state);
+        assert_eq!(interp.pseudo_random(), 0.75);
+    }
+}
@@ -0,0 +1,736 @@
+// ============================================================================
+// LANGUAGE SERVER
+// ============================================================================
+//
+// The Language Server Protocol for desktop editors, one JSON-RPC message at a
+// time, so the `yellow-lsp` binary only has to frame messages on stdio. Open
+// documents are checked as they change; completion, go-to-definition and
+// hover read a symbol table built from the tokens, which survives the half-
+// typed code an editor is usually holding, and the kinds inferred from the
+// syntax tree whenever the document parses. The WebAssembly build has no
+// editor to serve and leaves this module out.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::builtins;
+use crate::checker::{Diagnostic, Severity};
+use crate::json::{self, Json, Object};
+use crate::{printer, BinaryOperator, Expr, Interpreter, Lexer, Parser, Span, StandardPolicy, Stmt, StmtKind, Token};
+
+/// Every keyword the lexer knows, offered by completion.
+const KEYWORDS: [&str; 31] = [
+    "act", "scene", "mask", "echo", "Hastur", "Cassilda", "Carcosa", "pallid", "yellow", "tattered", "rewrite",
+    "remember", "forget", "superpose", "collapse", "infect", "whisper", "manifest", "entangle", "anchor", "rift",
+    "before", "during", "echoes", "peek", "oldest", "unearth", "fragments", "purge", "assert", "trial",
+];
+
+// LSP's CompletionItemKind and DiagnosticSeverity codes.
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+pub struct LanguageServer {
+    /// Open documents by URI, as the editor last sent them.
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+    /// The exit status once the client has sent `exit`.
+    exit: Option<i32>,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        LanguageServer { documents: BTreeMap::new(), shutdown: false, exit: None }
+    }
+
+    /// Set once the client has asked the server to exit: 0 after a `shutdown`
+    /// request, 1 without one.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Handles one message body and returns the bodies to send back: the
+    /// response to a request, and any notifications it caused.
+    pub fn handle(&mut self, message: &str) -> Vec<String> {
+        let message = match json::parse(message) {
+            Ok(message) => message,
+            Err(e) => return vec![failure("null", PARSE_ERROR, &e)],
+        };
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let Some(id) = message.get("id").map(id) else {
+            return self.notification(method, &params);
+        };
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok("null".to_string())
+            }
+            "textDocument/completion" => self.at(&params).map(|(doc, pos)| completion(doc, pos)),
+            "textDocument/definition" => self.at(&params).map(|(doc, pos)| definition(&uri(&params), doc, pos)),
+            "textDocument/hover" => self.at(&params).map(|(doc, pos)| hover(doc, pos)),
+            "textDocument/formatting" => self.document(&params).map(formatting),
+            _ => return vec![failure(&id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", method))],
+        };
+        match result {
+            Ok(result) => vec![Object::new().string("jsonrpc", "2.0").raw("id", &id).raw("result", &result).finish()],
+            Err(e) => vec![failure(&id, INVALID_PARAMS, &e)],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<String> {
+        let uri = uri(params);
+        match method {
+            "exit" => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|doc| doc.get("text")).and_then(Json::as_str);
+                self.documents.insert(uri.clone(), text.unwrap_or("").to_string());
+                vec![self.publish(&uri)]
+            }
+            // Only full sync is offered, so the last change holds the whole text.
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or(&[]);
+                match changes.last().and_then(|change| change.get("text")).and_then(Json::as_str) {
+                    Some(text) => {
+                        self.documents.insert(uri.clone(), text.to_string());
+                        vec![self.publish(&uri)]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![publish(&uri, "[]".to_string())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// `textDocument/publishDiagnostics` for an open document.
+    fn publish(&self, uri: &str) -> String {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let diagnostics = Interpreter::new().check(text);
+        publish(uri, json::array(diagnostics.iter().map(|diagnostic| lsp_diagnostic(diagnostic, &lines))))
+    }
+
+    fn document(&self, params: &Json) -> Result<&str, String> {
+        let uri = uri(params);
+        self.documents.get(&uri).map(String::as_str).ok_or_else(|| format!("'{}' is not open", uri))
+    }
+
+    /// The document a request is about and the position in it.
+    fn at(&self, params: &Json) -> Result<(&str, Span), String> {
+        let text = self.document(params)?;
+        let position = params.get("position").ok_or("The request has no position")?;
+        let line = position.get("line").and_then(Json::as_u64).ok_or("The position has no line")?;
+        let character = position.get("character").and_then(Json::as_u64).ok_or("The position has no character")?;
+        let line_text = text.split('\n').nth(line as usize).unwrap_or("");
+        Ok((text, Span { line: line as u32 + 1, column: column(line_text, character as usize) }))
+    }
+}
+
+// ============================================================================
+// PROTOCOL
+// ============================================================================
+
+/// A request id written back exactly as it came.
+fn id(id: &Json) -> String {
+    match id {
+        Json::Number(n) => n.clone(),
+        Json::String(s) => json::quote(s),
+        _ => "null".to_string(),
+    }
+}
+
+fn uri(params: &Json) -> String {
+    params.get("textDocument").and_then(|doc| doc.get("uri")).and_then(Json::as_str).unwrap_or("").to_string()
+}
+
+fn failure(id: &str, code: i32, message: &str) -> String {
+    let error = Object::new().raw("code", &code.to_string()).string("message", message).finish();
+    Object::new().string("jsonrpc", "2.0").raw("id", id).raw("error", &error).finish()
+}
+
+fn publish(uri: &str, diagnostics: String) -> String {
+    let params = Object::new().string("uri", uri).raw("diagnostics", &diagnostics).finish();
+    Object::new()
+        .string("jsonrpc", "2.0")
+        .string("method", "textDocument/publishDiagnostics")
+        .raw("params", &params)
+        .finish()
+}
+
+fn capabilities() -> String {
+    let capabilities = Object::new()
+        .raw("textDocumentSync", "1")
+        .raw("completionProvider", "{}")
+        .raw("definitionProvider", "true")
+        .raw("hoverProvider", "true")
+        .raw("documentFormattingProvider", "true")
+        .finish();
+    let info = Object::new().string("name", "yellow-lsp").string("version", env!("CARGO_PKG_VERSION")).finish();
+    Object::new().raw("capabilities", &capabilities).raw("serverInfo", &info).finish()
+}
+
+/// The lexer counts columns in characters from 1; LSP counts UTF-16 units from 0.
+fn column(line: &str, character: usize) -> u32 {
+    let mut units = 0;
+    let mut chars = 0;
+    for c in line.chars() {
+        if units >= character {
+            break;
+        }
+        units += c.len_utf16();
+        chars += 1;
+    }
+    chars + 1
+}
+
+fn character(line: &str, column: u32) -> usize {
+    line.chars().take(column.saturating_sub(1) as usize).map(char::len_utf16).sum()
+}
+
+fn position(lines: &[&str], span: Span) -> String {
+    let line = lines.get(span.line as usize - 1).copied().unwrap_or("");
+    Object::new()
+        .raw("line", &(span.line - 1).to_string())
+        .raw("character", &character(line, span.column).to_string())
+        .finish()
+}
+
+fn range(lines: &[&str], start: Span, end: Span) -> String {
+    Object::new().raw("start", &position(lines, start)).raw("end", &position(lines, end)).finish()
+}
+
+/// A diagnostic covers the rest of its line, since it is placed at the start of a statement.
+fn lsp_diagnostic(diagnostic: &Diagnostic, lines: &[&str]) -> String {
+    let start = Span { line: diagnostic.line, column: diagnostic.column };
+    let line = lines.get(diagnostic.line as usize - 1).copied().unwrap_or("");
+    let end = Span { line: diagnostic.line, column: (line.trim_end().chars().count() as u32 + 1).max(start.column) };
+    let severity = match diagnostic.severity {
+        Severity::Error => SEVERITY_ERROR,
+        Severity::Warning => SEVERITY_WARNING,
+    };
+    Object::new()
+        .raw("range", &range(lines, start, end))
+        .raw("severity", &severity.to_string())
+        .string("source", "yellow")
+        .string("message", &diagnostic.message)
+        .finish()
+}
+
+// ============================================================================
+// SYMBOLS
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Mask,
+    Act,
+    Param,
+}
+
+#[derive(Debug)]
+struct Symbol {
+    name: String,
+    kind: SymbolKind,
+    /// Where the name is first given.
+    span: Span,
+    /// Index into `Symbols::frames`.
+    frame: usize,
+    /// An act's parameters, or the act a parameter belongs to.
+    params: Vec<String>,
+    owner: Option<String>,
+    /// What the name may hold, as far as the syntax tree tells.
+    kinds: BTreeSet<String>,
+}
+
+/// A frame of variables: the whole document, or an act's or scene's body.
+#[derive(Debug)]
+struct Frame {
+    start: Span,
+    /// Where its closing brace is; `None` while the brace is still missing.
+    end: Option<Span>,
+}
+
+impl Frame {
+    fn contains(&self, pos: Span) -> bool {
+        key(self.start) <= key(pos) && self.end.is_none_or(|end| key(pos) <= key(end))
+    }
+}
+
+fn key(span: Span) -> (u32, u32) {
+    (span.line, span.column)
+}
+
+struct Symbols {
+    frames: Vec<Frame>,
+    symbols: Vec<Symbol>,
+    /// Identifiers with where they start, for finding the one under the cursor.
+    identifiers: Vec<(String, Span)>,
+}
+
+impl Symbols {
+    /// Reads the symbols off the tokens, with kinds from the syntax tree if the text parses.
+    fn new(text: &str) -> Self {
+        let tokens = Lexer::new(text).tokenize();
+        let mut inference = Inference::default();
+        for (name, _, returns) in builtins::signatures() {
+            inference.returns.insert(name.to_string(), [returns.to_string()].into());
+        }
+        if let Ok(program) = Parser::with_policy(tokens.clone(), &StandardPolicy).parse_program() {
+            inference.block(&program);
+        }
+        let mut table = Symbols { frames: vec![Frame { start: Span { line: 1, column: 1 }, end: None }], symbols: Vec::new(), identifiers: Vec::new() };
+        // Open braces, each with the frame it opened if it opened one.
+        let mut braces: Vec<Option<usize>> = Vec::new();
+        let mut frame = 0;
+        // A frame waiting for its `{`, opened by `act` or `scene`.
+        let mut pending: Option<usize> = None;
+        let mut i = 0;
+        while i < tokens.len() {
+            let spanned = &tokens[i];
+            match &spanned.token {
+                Token::Identifier(name) => table.identifiers.push((name.clone(), spanned.span)),
+                Token::Mask => {
+                    if let Some(Token::Identifier(name)) = tokens.get(i + 1).map(|t| &t.token) {
+                        let mask = table.define(name, SymbolKind::Mask, tokens[i + 1].span, frame, None);
+                        if let Some(kinds) = inference.masks.get(&key(spanned.span)) {
+                            table.symbols[mask].kinds.extend(kinds.iter().cloned());
+                        }
+                    }
+                }
+                Token::Scene => {
+                    pending = Some(table.open(spanned.span));
+                }
+                Token::Act => {
+                    let Some(Token::Identifier(name)) = tokens.get(i + 1).map(|t| &t.token) else {
+                        i += 1;
+                        continue;
+                    };
+                    let act = table.define(name, SymbolKind::Act, tokens[i + 1].span, frame, None);
+                    if let Some(kinds) = inference.returns.get(name) {
+                        table.symbols[act].kinds.extend(kinds.iter().cloned());
+                    }
+                    let body = table.open(spanned.span);
+                    pending = Some(body);
+                    table.identifiers.push((name.clone(), tokens[i + 1].span));
+                    i += 2;
+                    if tokens.get(i).map(|t| &t.token) == Some(&Token::LParen) {
+                        i += 1;
+                        while let Some(Token::Identifier(param)) = tokens.get(i).map(|t| &t.token) {
+                            table.identifiers.push((param.clone(), tokens[i].span));
+                            table.define(param, SymbolKind::Param, tokens[i].span, body, Some(name.clone()));
+                            table.symbols[act].params.push(param.clone());
+                            i += 1;
+                            if tokens.get(i).map(|t| &t.token) == Some(&Token::Comma) {
+                                i += 1;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Token::LBrace => {
+                    let opened = pending.take();
+                    braces.push(opened);
+                    if let Some(opened) = opened {
+                        frame = opened;
+                    }
+                }
+                Token::RBrace => {
+                    if let Some(Some(closed)) = braces.pop() {
+                        table.frames[closed].end = Some(spanned.span);
+                        frame = braces.iter().rev().find_map(|b| *b).unwrap_or(0);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        table
+    }
+
+    fn open(&mut self, start: Span) -> usize {
+        self.frames.push(Frame { start, end: None });
+        self.frames.len() - 1
+    }
+
+    /// Adds a symbol, or returns the one this frame already has by that name.
+    fn define(&mut self, name: &str, kind: SymbolKind, span: Span, frame: usize, owner: Option<String>) -> usize {
+        if let Some(index) = self.symbols.iter().position(|s| s.frame == frame && s.name == name) {
+            return index;
+        }
+        self.symbols.push(Symbol { name: name.to_string(), kind, span, frame, params: Vec::new(), owner, kinds: BTreeSet::new() });
+        self.symbols.len() - 1
+    }
+
+    /// Only the innermost frame and the globals are visible, innermost first.
+    fn visible(&self, pos: Span) -> Vec<usize> {
+        let innermost = (1..self.frames.len()).rev().filter(|&f| self.frames[f].contains(pos)).max_by_key(|&f| key(self.frames[f].start));
+        innermost.into_iter().chain([0]).collect()
+    }
+
+    fn lookup(&self, name: &str, pos: Span) -> Option<&Symbol> {
+        self.visible(pos)
+            .into_iter()
+            .find_map(|frame| self.symbols.iter().find(|s| s.frame == frame && s.name == name))
+    }
+
+    /// The identifier the cursor is on or just after.
+    fn identifier_at(&self, pos: Span) -> Option<&str> {
+        self.identifiers
+            .iter()
+            .find(|(name, span)| {
+                span.line == pos.line && span.column <= pos.column && pos.column <= span.column + name.chars().count() as u32
+            })
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl Symbol {
+    fn describe(&self) -> String {
+        let kinds = if self.kinds.is_empty() {
+            "unknown".to_string()
+        } else {
+            self.kinds.iter().cloned().collect::<Vec<_>>().join(" or ")
+        };
+        match self.kind {
+            SymbolKind::Mask => format!("mask {}: {}", self.name, kinds),
+            SymbolKind::Act => format!("act {}({}) returns {}", self.name, self.params.join(", "), kinds),
+            SymbolKind::Param => format!("parameter {} of act {}", self.name, self.owner.as_deref().unwrap_or("?")),
+        }
+    }
+}
+
+/// What each mask may hold and each act may return, judged from literals and
+/// operators. Anything read back from memory or time is unknown.
+#[derive(Default)]
+struct Inference {
+    /// By the span of the `mask` statement.
+    masks: BTreeMap<(u32, u32), BTreeSet<String>>,
+    returns: BTreeMap<String, BTreeSet<String>>,
+    /// Kinds known so far for each name, for reading identifiers.
+    names: BTreeMap<String, BTreeSet<String>>,
+    /// The Carcosa kinds of the act being walked.
+    carcosa: Vec<BTreeSet<String>>,
+}
+
+impl Inference {
+    fn block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Mask { name, value, .. } => {
+                let kinds = self.expression(value);
+                self.names.entry(name.clone()).or_default().extend(kinds.iter().cloned());
+                self.masks.entry(key(stmt.span)).or_default().extend(kinds);
+            }
+            StmtKind::Act { name, act } => {
+                self.names.entry(name.clone()).or_default().insert("act".to_string());
+                self.carcosa.push(BTreeSet::new());
+                self.block(&act.body);
+                let mut returns = self.carcosa.pop().unwrap_or_default();
+                if !matches!(act.body.last().map(|s| &s.kind), Some(StmtKind::Carcosa(_))) {
+                    returns.insert("pallid".to_string());
+                }
+                self.returns.insert(name.clone(), returns);
+            }
+            StmtKind::Carcosa(value) => {
+                let kinds = match value {
+                    Some(value) => self.expression(value),
+                    None => ["pallid".to_string()].into(),
+                };
+                if let Some(carcosa) = self.carcosa.last_mut() {
+                    carcosa.extend(kinds);
+                }
+            }
+            StmtKind::Scene { body, .. } | StmtKind::Trial { body, .. } => self.block(body),
+            StmtKind::Hastur { body, .. } => self.block(body),
+            StmtKind::Cassilda { then_branch, else_branch, .. } => {
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> BTreeSet<String> {
+        let kind = match expr {
+            Expr::Number(_) | Expr::Fragments(_) => "number",
+            Expr::String(_) => "string",
+            Expr::Boolean(_) => "boolean",
+            Expr::Superpose(_) => "superposition",
+            Expr::Entangle(..) => "entangled",
+            Expr::BinaryOp { left, op, right } => match op {
+                BinaryOperator::Eq | BinaryOperator::Neq | BinaryOperator::Gt | BinaryOperator::Lt => "boolean",
+                // Numbers add and strings join; anything else is a type mismatch.
+                BinaryOperator::Add => {
+                    let (left, right) = (self.expression(left), self.expression(right));
+                    let side = |kinds: &BTreeSet<String>| -> BTreeSet<String> {
+                        if kinds.is_empty() {
+                            ["number".to_string(), "string".to_string()].into()
+                        } else {
+                            kinds.iter().filter(|k| *k == "number" || *k == "string").cloned().collect()
+                        }
+                    };
+                    return side(&left).intersection(&side(&right)).cloned().collect();
+                }
+                _ => "number",
+            },
+            Expr::Identifier(name, _) => return self.names.get(name).cloned().unwrap_or_default(),
+            Expr::Call { name, .. } => return self.returns.get(name).cloned().unwrap_or_default(),
+            Expr::Rewrite { target: expr } | Expr::Rift(expr) => return self.expression(expr),
+            Expr::Collapse(expr) => match &**expr {
+                Expr::Superpose(exprs) => return exprs.iter().flat_map(|e| self.expression(e)).collect(),
+                _ => return BTreeSet::new(),
+            },
+            Expr::Echoes(_) => "history",
+            Expr::Manifest(_) | Expr::Peek(_) | Expr::Oldest(_) | Expr::Unearth(_) | Expr::Before(..) | Expr::During(..) => {
+                return BTreeSet::new()
+            }
+        };
+        [kind.to_string()].into()
+    }
+}
+
+// ============================================================================
+// REQUESTS
+// ============================================================================
+
+fn completion(text: &str, pos: Span) -> String {
+    let table = Symbols::new(text);
+    let mut items: Vec<String> = KEYWORDS
+        .iter()
+        .map(|keyword| {
+            Object::new()
+                .string("label", keyword)
+                .raw("kind", &COMPLETION_KEYWORD.to_string())
+                .string("detail", "keyword")
+                .finish()
+        })
+        .collect();
+    for (name, params, returns) in builtins::signatures() {
+        items.push(
+            Object::new()
+                .string("label", name)
+                .raw("kind", &COMPLETION_FUNCTION.to_string())
+                .string("detail", &describe_builtin(name, params, returns))
+                .finish(),
+        );
+    }
+    let frames = table.visible(pos);
+    // Act bodies run after the whole program is read, so every global is in reach there.
+    let in_act = frames.len() > 1;
+    for symbol in &table.symbols {
+        let reachable = frames.contains(&symbol.frame)
+            && (symbol.kind == SymbolKind::Param || (in_act && symbol.frame == 0) || key(symbol.span) < key(pos));
+        if !reachable {
+            continue;
+        }
+        let kind = if symbol.kind == SymbolKind::Act { COMPLETION_FUNCTION } else { COMPLETION_VARIABLE };
+        items.push(
+            Object::new()
+                .string("label", &symbol.name)
+                .raw("kind", &kind.to_string())
+                .string("detail", &symbol.describe())
+                .finish(),
+        );
+    }
+    json::array(items)
+}
+
+fn definition(uri: &str, text: &str, pos: Span) -> String {
+    let table = Symbols::new(text);
+    let Some(symbol) = table.identifier_at(pos).and_then(|name| table.lookup(name, pos)) else {
+        return "null".to_string();
+    };
+    let lines: Vec<&str> = text.split('\n').collect();
+    let end = Span { line: symbol.span.line, column: symbol.span.column + symbol.name.chars().count() as u32 };
+    Object::new().string("uri", uri).raw("range", &range(&lines, symbol.span, end)).finish()
+}
+
+fn describe_builtin(name: &str, params: &[&str], returns: &str) -> String {
+    format!("built-in act {}({}) returns {}", name, params.join(", "), returns)
+}
+
+fn hover(text: &str, pos: Span) -> String {
+    let table = Symbols::new(text);
+    let Some(name) = table.identifier_at(pos) else {
+        return "null".to_string();
+    };
+    let description = match table.lookup(name, pos) {
+        Some(symbol) => symbol.describe(),
+        None => match builtins::signatures().find(|(builtin, ..)| *builtin == name) {
+            Some((name, params, returns)) => describe_builtin(name, params, returns),
+            None => return "null".to_string(),
+        },
+    };
+    let contents = Object::new()
+        .string("kind", "markdown")
+        .string("value", &format!("```yellow\n{}\n```", description))
+        .finish();
+    Object::new().raw("contents", &contents).finish()
+}
+
+/// One edit replacing the whole document, or none if it is already tidy or does not parse.
+fn formatting(text: &str) -> String {
+    let formatted = match printer::format(text) {
+        Ok(formatted) if formatted != text => formatted,
+        _ => return "[]".to_string(),
+    };
+    let lines: Vec<&str> = text.split('\n').collect();
+    let last = lines.len() as u32;
+    let end = Span { line: last, column: lines[lines.len() - 1].chars().count() as u32 + 1 };
+    let edit = Object::new()
+        .raw("range", &range(&lines, Span { line: 1, column: 1 }, end))
+        .string("newText", &formatted)
+        .finish();
+    json::array([edit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///a.yellow";
+    const TEXT: &str = "act double(n) {\n    Carcosa n * 2;\n}\nmask a -> double(2);\necho(ghost);\n";
+
+    fn opened(text: &str) -> (LanguageServer, Json) {
+        let mut server = LanguageServer::new();
+        let document = Object::new().string("uri", URI).string("text", text).finish();
+        let params = Object::new().raw("textDocument", &document).finish();
+        let mut sent = server.handle(&notification("textDocument/didOpen", &params));
+        assert_eq!(sent.len(), 1);
+        (server, json::parse(&sent.remove(0)).unwrap())
+    }
+
+    fn notification(method: &str, params: &str) -> String {
+        Object::new().string("jsonrpc", "2.0").string("method", method).raw("params", params).finish()
+    }
+
+    fn request(server: &mut LanguageServer, method: &str, params: &str) -> Json {
+        let message = Object::new().string("jsonrpc", "2.0").raw("id", "7").string("method", method).raw("params", params);
+        let mut sent = server.handle(&message.finish());
+        assert_eq!(sent.len(), 1);
+        let response = json::parse(&sent.remove(0)).unwrap();
+        assert_eq!(response.get("id").and_then(Json::as_u64), Some(7));
+        response
+    }
+
+    fn at(line: u32, character: u32) -> String {
+        let position = Object::new().raw("line", &line.to_string()).raw("character", &character.to_string()).finish();
+        let document = Object::new().string("uri", URI).finish();
+        Object::new().raw("textDocument", &document).raw("position", &position).finish()
+    }
+
+    fn number(json: &Json, path: &[&str]) -> Option<u64> {
+        path.iter().try_fold(json, |json, key| json.get(key)).and_then(Json::as_u64)
+    }
+
+    fn code(response: &Json) -> Option<i32> {
+        response.get("error")?.get("code")?.as_f64().map(|code| code as i32)
+    }
+
+    #[test]
+    fn initialize_lists_capabilities() {
+        let response = request(&mut LanguageServer::new(), "initialize", "{}");
+        let capabilities = response.get("result").and_then(|result| result.get("capabilities")).unwrap();
+        assert_eq!(capabilities.get("hoverProvider").and_then(Json::as_bool), Some(true));
+        assert_eq!(capabilities.get("documentFormattingProvider").and_then(Json::as_bool), Some(true));
+    }
+
+    #[test]
+    fn opening_a_document_publishes_its_diagnostics() {
+        let (_, published) = opened(TEXT);
+        assert_eq!(published.get("method").and_then(Json::as_str), Some("textDocument/publishDiagnostics"));
+        let diagnostics = published.get("params").and_then(|params| params.get("diagnostics")).unwrap();
+        let [diagnostic] = diagnostics.as_array().unwrap() else { panic!("expected one diagnostic") };
+        assert_eq!(diagnostic.get("severity").and_then(Json::as_u64), Some(2));
+        assert_eq!(number(diagnostic, &["range", "start", "line"]), Some(4));
+        assert!(diagnostic.get("message").and_then(Json::as_str).unwrap().contains("'ghost'"));
+    }
+
+    #[test]
+    fn definition_and_hover_find_the_act() {
+        let (mut server, _) = opened(TEXT);
+        let definition = request(&mut server, "textDocument/definition", &at(3, 11));
+        let result = definition.get("result").unwrap();
+        assert_eq!(result.get("uri").and_then(Json::as_str), Some(URI));
+        assert_eq!(number(result, &["range", "start", "line"]), Some(0));
+        assert_eq!(number(result, &["range", "start", "character"]), Some(4));
+        assert_eq!(number(result, &["range", "end", "character"]), Some(10));
+
+        let hover = request(&mut server, "textDocument/hover", &at(3, 11));
+        let contents = hover.get("result").and_then(|result| result.get("contents")).unwrap();
+        assert!(contents.get("value").and_then(Json::as_str).unwrap().contains("act double(n) returns number"));
+    }
+
+    #[test]
+    fn completion_offers_keywords_acts_and_masks() {
+        let (mut server, _) = opened(TEXT);
+        let completion = request(&mut server, "textDocument/completion", &at(4, 0));
+        let items = completion.get("result").and_then(Json::as_array).unwrap();
+        let kind = |label: &str| {
+            let item = items.iter().find(|item| item.get("label").and_then(Json::as_str) == Some(label))?;
+            item.get("kind")?.as_u64()
+        };
+        assert_eq!(kind("unearth"), Some(COMPLETION_KEYWORD as u64));
+        assert_eq!(kind("double"), Some(COMPLETION_FUNCTION as u64));
+        assert_eq!(kind("length"), Some(COMPLETION_FUNCTION as u64));
+        assert_eq!(kind("a"), Some(COMPLETION_VARIABLE as u64));
+    }
+
+    #[test]
+    fn formatting_replaces_the_whole_document() {
+        let (mut server, _) = opened("mask a->1;\necho( a );\n");
+        let response = request(&mut server, "textDocument/formatting", &at(0, 0));
+        let [edit] = response.get("result").and_then(Json::as_array).unwrap() else { panic!("expected one edit") };
+        assert_eq!(edit.get("newText").and_then(Json::as_str), Some("mask a -> 1;\necho(a);\n"));
+        assert_eq!(number(edit, &["range", "end", "line"]), Some(2));
+    }
+
+    #[test]
+    fn unknown_methods_and_unopened_documents_fail() {
+        let mut server = LanguageServer::new();
+        let unknown = request(&mut server, "textDocument/rename", "{}");
+        assert_eq!(code(&unknown), Some(METHOD_NOT_FOUND));
+        let unopened = request(&mut server, "textDocument/hover", &at(0, 0));
+        assert_eq!(code(&unopened), Some(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn exit_status_depends_on_shutdown() {
+        let mut server = LanguageServer::new();
+        assert!(server.handle(&notification("exit", "{}")).is_empty());
+        assert_eq!(server.exit_code(), Some(1));
+
+        let mut server = LanguageServer::new();
+        request(&mut server, "shutdown", "null");
+        server.handle(&notification("exit", "{}"));
+        assert_eq!(server.exit_code(), Some(0));
+    }
+}
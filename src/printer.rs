@@ -0,0 +1,284 @@
+// ============================================================================
+// PRINTER
+// ============================================================================
+//
+// Turns a syntax tree back into Yellow source that parses to the same tree,
+// laid out afresh: four spaces per level, one statement a line. `format` does
+// the same for a whole source file and puts its comments back, taken from the
+// lexer's trivia, keeping single blank lines between statements.
+
+use std::fmt::Write;
+
+use crate::{BinaryOperator, Comment, Expr, Lexer, Parser, StandardPolicy, Stmt, StmtKind};
+
+const INDENT: &str = "    ";
+
+struct Printer<'a> {
+    out: String,
+    /// Comments still to be placed, in source order.
+    comments: &'a [Comment],
+    /// Whether to keep the source's blank lines, which only mean something when
+    /// printing the file the statements were parsed from.
+    layout: bool,
+    /// The source line printed last, unless a block has just opened.
+    last_line: Option<u32>,
+}
+
+impl Printer<'_> {
+    fn new(comments: &[Comment], layout: bool) -> Printer<'_> {
+        Printer { out: String::new(), comments, layout, last_line: None }
+    }
+
+    /// A blank line, if the source had one before `line`.
+    fn gap(&mut self, line: u32) {
+        if self.layout && self.last_line.is_some_and(|last| line > last + 1) {
+            self.out.push('\n');
+        }
+        self.last_line = Some(line);
+    }
+
+    /// Comments that start before `line`, each on its own line.
+    fn comments_before(&mut self, line: u32, depth: usize) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.line >= line {
+                break;
+            }
+            self.comments = rest;
+            self.gap(comment.span.line);
+            let _ = writeln!(self.out, "{}#{}", INDENT.repeat(depth), comment.text);
+        }
+    }
+
+    /// The comment at the end of a statement's last line goes after it; any others
+    /// within the statement follow on their own lines.
+    fn trailing(&mut self, through: u32, depth: usize) {
+        if let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.line <= through {
+                self.comments = rest;
+                self.out.pop();
+                let _ = writeln!(self.out, " #{}", comment.text);
+                self.comments_before(through + 1, depth);
+            }
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Stmt], depth: usize) {
+        for stmt in stmts {
+            self.statement(stmt, depth);
+        }
+    }
+
+    fn block(&mut self, head: &str, body: &[Stmt], stmt: &Stmt, depth: usize) {
+        let _ = writeln!(self.out, "{} {{", head);
+        // A block on one line keeps its comment for the statement inside.
+        if stmt.end.line > stmt.span.line {
+            self.trailing(stmt.span.line, depth);
+        }
+        self.last_line = None;
+        self.statements(body, depth + 1);
+        self.comments_before(stmt.end.line, depth + 1);
+        self.out.push_str(&INDENT.repeat(depth));
+        self.out.push_str("}\n");
+        self.trailing(stmt.end.line, depth);
+        self.last_line = Some(stmt.end.line);
+    }
+
+    fn statement(&mut self, stmt: &Stmt, depth: usize) {
+        self.comments_before(stmt.span.line, depth);
+        self.gap(stmt.span.line);
+        self.out.push_str(&INDENT.repeat(depth));
+        match &stmt.kind {
+            StmtKind::Mask { name, value, .. } => {
+                let _ = writeln!(self.out, "mask {} -> {};", name, expression(value));
+            }
+            StmtKind::Echo(expr) => {
+                let _ = writeln!(self.out, "echo({});", expression(expr));
+            }
+            StmtKind::Scene { body, .. } => return self.block("scene", body, stmt, depth),
+            StmtKind::Hastur { condition, body, is_rift } => {
+                let keyword = if *is_rift { "rift" } else { "Hastur" };
+                return self.block(&format!("{} ({})", keyword, expression(condition)), body, stmt, depth);
+            }
+            // The grammar has no `else`, so the parser never fills `else_branch`.
+            StmtKind::Cassilda { condition, then_branch, .. } => {
+                return self.block(&format!("Cassilda ({})", expression(condition)), then_branch, stmt, depth);
+            }
+            StmtKind::Carcosa(None) => self.out.push_str("Carcosa;\n"),
+            StmtKind::Carcosa(Some(value)) => {
+                let _ = writeln!(self.out, "Carcosa {};", expression(value));
+            }
+            StmtKind::Act { name, act } => {
+                return self.block(&format!("act {}({})", name, act.params().join(", ")), &act.body, stmt, depth);
+            }
+            StmtKind::Rewrite { target } => {
+                let _ = writeln!(self.out, "rewrite {};", target);
+            }
+            StmtKind::Remember { name, value: None } => {
+                let _ = writeln!(self.out, "remember {};", name);
+            }
+            StmtKind::Remember { name, value: Some(value) } => {
+                let _ = writeln!(self.out, "remember {} -> {};", name, expression(value));
+            }
+            StmtKind::Purge(name) => {
+                let _ = writeln!(self.out, "purge {};", name);
+            }
+            StmtKind::Forget(name) => {
+                let _ = writeln!(self.out, "forget {};", name);
+            }
+            StmtKind::Infect(name) => {
+                let _ = writeln!(self.out, "infect {};", name);
+            }
+            StmtKind::Whisper(code) => {
+                let _ = writeln!(self.out, "whisper \"{}\";", code);
+            }
+            StmtKind::Anchor => self.out.push_str("anchor;\n"),
+            StmtKind::Assert(condition) => {
+                let _ = writeln!(self.out, "assert {};", expression(condition));
+            }
+            StmtKind::Trial { name, body } => return self.block(&format!("trial \"{}\"", name), body, stmt, depth),
+            StmtKind::ExprStmt(expr) => {
+                let text = expression(expr);
+                // A statement opening with `rewrite` or `rift` would parse as the statement form.
+                if text.starts_with("rewrite ") || text.starts_with("rift(") {
+                    let _ = writeln!(self.out, "({});", text);
+                } else {
+                    let _ = writeln!(self.out, "{};", text);
+                }
+            }
+        }
+        self.trailing(stmt.end.line, depth);
+        self.last_line = Some(stmt.end.line);
+    }
+}
+
+pub(crate) fn statements(stmts: &[Stmt], depth: usize) -> String {
+    let mut printer = Printer::new(&[], false);
+    printer.statements(stmts, depth);
+    printer.out
+}
+
+/// `source` in canonical layout, comments included. Fails if it does not parse.
+pub(crate) fn format(source: &str) -> Result<String, String> {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::with_policy(tokens, &StandardPolicy);
+    let stmts = parser.parse_program().map_err(|e| format!("Line {}: {}", parser.span().line, e))?;
+    let mut printer = Printer::new(&lexer.comments, true);
+    printer.statements(&stmts, 0);
+    printer.comments_before(u32::MAX, 0);
+    Ok(printer.out)
+}
+
+/// Binding strength, loosest first, matching the parser's comparison/term/factor levels.
+fn precedence(op: BinaryOperator) -> u8 {
+    match op {
+        BinaryOperator::Eq | BinaryOperator::Neq | BinaryOperator::Gt | BinaryOperator::Lt => 0,
+        BinaryOperator::Add | BinaryOperator::Sub => 1,
+        BinaryOperator::Mul | BinaryOperator::Div => 2,
+    }
+}
+
+fn symbol(op: BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Eq => "==",
+        BinaryOperator::Neq => "!=",
+        BinaryOperator::Gt => ">",
+        BinaryOperator::Lt => "<",
+    }
+}
+
+/// `expr`, parenthesized unless it binds at least as tightly as `floor`.
+fn operand(expr: &Expr, floor: u8) -> String {
+    match expr {
+        Expr::BinaryOp { op, .. } if precedence(*op) < floor => format!("({})", expression(expr)),
+        _ => expression(expr),
+    }
+}
+
+fn list(exprs: &[Expr]) -> String {
+    exprs.iter().map(expression).collect::<Vec<_>>().join(", ")
+}
+
+pub(crate) fn expression(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => format!("{}", n),
+        Expr::String(s) => format!("\"{}\"", s),
+        Expr::Boolean(true) => "yellow".to_string(),
+        Expr::Boolean(false) => "tattered".to_string(),
+        Expr::Identifier(name, _) => name.clone(),
+        // Operators are left-associative, so the right operand needs parentheses one level sooner.
+        Expr::BinaryOp { left, op, right } => {
+            let level = precedence(*op);
+            format!("{} {} {}", operand(left, level), symbol(*op), operand(right, level + 1))
+        }
+        Expr::Call { name, args } => format!("{}({})", name, list(args)),
+        Expr::Rewrite { target } => format!("rewrite {}", operand(target, u8::MAX)),
+        Expr::Superpose(exprs) => format!("superpose({})", list(exprs)),
+        Expr::Collapse(expr) => format!("collapse({})", expression(expr)),
+        Expr::Manifest(name) => format!("manifest({})", name),
+        Expr::Entangle(a, b) => format!("entangle({}, {})", a, b),
+        Expr::Rift(expr) => format!("rift({})", expression(expr)),
+        Expr::Before(name, when) => format!("before({}, {})", name, expression(when)),
+        Expr::During(name, when) => format!("during({}, {})", name, expression(when)),
+        Expr::Echoes(name) => format!("echoes({})", name),
+        Expr::Peek(name) => format!("peek({})", name),
+        Expr::Oldest(name) => format!("oldest({})", name),
+        Expr::Unearth(name) => format!("unearth({})", name),
+        Expr::Fragments(name) => format!("fragments({})", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "# heading\nmask a->1; # trailing\nact f(n){\n# inside\nCassilda(n<2){Carcosa n;}\nCarcosa (n-1)*2; # doubled\n}\n\n\necho(f(a+2)*3);\n# last words\n";
+
+    const TIDY: &str = "\
+# heading
+mask a -> 1; # trailing
+act f(n) {
+    # inside
+    Cassilda (n < 2) {
+        Carcosa n;
+    }
+    Carcosa (n - 1) * 2; # doubled
+}
+
+echo(f(a + 2) * 3);
+# last words
+";
+
+    #[test]
+    fn lays_out_code_and_keeps_comments_in_place() {
+        assert_eq!(format(MESSY).unwrap(), TIDY);
+    }
+
+    #[test]
+    fn formatting_twice_changes_nothing() {
+        assert_eq!(format(TIDY).unwrap(), TIDY);
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "yellow") {
+                let once = format(&std::fs::read_to_string(&path).unwrap()).unwrap();
+                assert_eq!(format(&once).unwrap(), once, "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn parentheses_follow_precedence() {
+        assert_eq!(format("echo((a*b)+(c-d)*e);").unwrap(), "echo(a * b + (c - d) * e);\n");
+        assert_eq!(format("echo(a-(b-c));").unwrap(), "echo(a - (b - c));\n");
+    }
+
+    #[test]
+    fn code_that_does_not_parse_is_left_alone() {
+        assert_eq!(format("mask a -> ;").unwrap_err(), "Line 1: Syntax Error");
+    }
+}
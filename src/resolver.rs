@@ -0,0 +1,359 @@
+// ============================================================================
+// RESOLVER
+// ============================================================================
+//
+// Runs between parsing and execution. Every scene and act body gets a slot
+// layout for the names it assigns, and reads and masks of those names are
+// tagged with their slot, so frames are indexed instead of searched.
+// Anything the resolver cannot see — globals, whispers, echoes bleeding
+// through, phantoms — stays `Slot::Dynamic` and is looked up by name.
+//
+// Infections and phantoms stay keyed by name, out of this pass's reach. An
+// infection spreads at random to whatever names the frame holds when it runs,
+// and follows the name rather than the frame; phantoms appear under names no
+// statement mentions, and are only consulted once sanity is low. Neither has
+// a place to lay out ahead of time. A `Slot::Dynamic` read still searches the
+// frame's layout, which holds only the names that frame assigns.
+//
+// Along the way it reports reads of names nothing defines, for `check` and
+// the language server; runs ignore them. These are only warnings: a whisper
+// or a bleeding echo may still conjure the name in time.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use crate::{Expr, Slot, Span, Stmt, StmtKind, Value};
+
+/// Tags `program` with slots and returns its "undefined variable" diagnostics, each with
+/// the statement it was found in. `globals` are the names already defined by earlier runs.
+pub(crate) fn resolve(program: &mut [Stmt], globals: &BTreeMap<String, Value>) -> Vec<(Span, String)> {
+    let mut program_globals = Vec::new();
+    assigned(program, &mut program_globals);
+    let mut resolver = Resolver {
+        globals,
+        program_globals: program_globals.into_iter().collect(),
+        defined: BTreeSet::new(),
+        frames: Vec::new(),
+        deferred: 0,
+        whispered: false,
+        span: Span::default(),
+        reported: BTreeSet::new(),
+        diagnostics: Vec::new(),
+    };
+    resolver.block(program);
+    resolver.diagnostics
+}
+
+struct Layout {
+    names: Vec<String>,
+    /// Names assigned so far in this frame.
+    defined: BTreeSet<String>,
+}
+
+struct Resolver<'a> {
+    globals: &'a BTreeMap<String, Value>,
+    /// Every name the top level assigns; an act may be called after any of them.
+    program_globals: BTreeSet<String>,
+    /// Top-level names assigned so far, in program order.
+    defined: BTreeSet<String>,
+    frames: Vec<Layout>,
+    /// How many act bodies enclose the code being resolved.
+    deferred: usize,
+    /// Once a whisper has run, any name may exist.
+    whispered: bool,
+    /// The statement being resolved.
+    span: Span,
+    reported: BTreeSet<String>,
+    diagnostics: Vec<(Span, String)>,
+}
+
+impl Resolver<'_> {
+    fn block(&mut self, statements: &mut [Stmt]) {
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Stmt) {
+        self.span = stmt.span;
+        match &mut stmt.kind {
+            StmtKind::Mask { name, slot, value } => {
+                self.expression(value);
+                *slot = self.define(name);
+            }
+            StmtKind::Echo(expr) | StmtKind::ExprStmt(expr) | StmtKind::Assert(expr) => self.expression(expr),
+            // A trial runs at the top level once the whole program has, so every top-level
+            // name is defined by then, and what it masks stays its own.
+            StmtKind::Trial { body, .. } => {
+                let defined = std::mem::replace(&mut self.defined, self.program_globals.clone());
+                let whispered = self.whispered;
+                self.block(body);
+                self.defined = defined;
+                self.whispered = whispered;
+            }
+            StmtKind::Scene { body, locals } => {
+                let mut names = Vec::new();
+                assigned(body, &mut names);
+                *locals = Rc::from(names.clone());
+                self.frames.push(Layout { names, defined: BTreeSet::new() });
+                self.block(body);
+                self.frames.pop();
+            }
+            StmtKind::Hastur { condition, body, .. } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            StmtKind::Cassilda { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            StmtKind::Carcosa(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(expr);
+                }
+            }
+            StmtKind::Act { name, act } => {
+                self.define(name);
+                // Freshly parsed acts are never shared; one that is keeps resolving by name.
+                let Some(act) = Rc::get_mut(act) else { return };
+                let mut names = act.params().to_vec();
+                assigned(&act.body, &mut names);
+                act.locals = Rc::from(names.clone());
+                let defined = act.params().iter().cloned().collect();
+                self.frames.push(Layout { names, defined });
+                self.deferred += 1;
+                self.block(&mut act.body);
+                self.deferred -= 1;
+                self.frames.pop();
+            }
+            StmtKind::Remember { value: Some(expr), .. } => self.expression(expr),
+            StmtKind::Whisper(_) => self.whispered = true,
+            StmtKind::Rewrite { .. }
+            | StmtKind::Remember { value: None, .. }
+            | StmtKind::Purge(_)
+            | StmtKind::Forget(_)
+            | StmtKind::Infect(_)
+            | StmtKind::Anchor => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Identifier(name, slot) => {
+                if !self.is_defined(name) {
+                    self.report(name, format!("Undefined: '{}' is read before anything masks it", name));
+                }
+                *slot = self.slot(name);
+            }
+            Expr::Call { name, args } => {
+                for arg in args.iter_mut() {
+                    self.expression(arg);
+                }
+                if !self.is_defined(name) {
+                    self.report(name, format!("Unknown act: '{}' is called before it is defined", name));
+                }
+            }
+            Expr::BinaryOp { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Superpose(exprs) => {
+                for e in exprs {
+                    self.expression(e);
+                }
+            }
+            Expr::Rewrite { target: expr }
+            | Expr::Collapse(expr)
+            | Expr::Rift(expr)
+            | Expr::Before(_, expr)
+            | Expr::During(_, expr) => self.expression(expr),
+            Expr::Entangle(var1, _) => {
+                self.define(var1);
+            }
+            Expr::Number(_)
+            | Expr::String(_)
+            | Expr::Boolean(_)
+            | Expr::Manifest(_)
+            | Expr::Echoes(_)
+            | Expr::Peek(_)
+            | Expr::Oldest(_)
+            | Expr::Unearth(_)
+            | Expr::Fragments(_) => {}
+        }
+    }
+
+    fn slot(&self, name: &str) -> Slot {
+        self.frames
+            .last()
+            .and_then(|frame| frame.names.iter().position(|n| n == name))
+            .map_or(Slot::Dynamic, |idx| Slot::Local(idx as u32))
+    }
+
+    /// Records an assignment and returns where it lands.
+    fn define(&mut self, name: &str) -> Slot {
+        match self.frames.last_mut() {
+            Some(frame) => {
+                frame.defined.insert(name.to_string());
+            }
+            None => {
+                self.defined.insert(name.to_string());
+            }
+        }
+        self.slot(name)
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        if self.whispered || self.globals.contains_key(name) {
+            return true;
+        }
+        if let Some(frame) = self.frames.last() {
+            if frame.defined.contains(name) {
+                return true;
+            }
+        }
+        // Act bodies run whenever they are called, so any top-level name may exist by then.
+        if self.deferred > 0 {
+            self.program_globals.contains(name)
+        } else {
+            self.defined.contains(name)
+        }
+    }
+
+    fn report(&mut self, name: &str, message: String) {
+        if self.reported.insert(name.to_string()) {
+            self.diagnostics.push((self.span, message));
+        }
+    }
+}
+
+/// Collects the names `statements` assign in their own frame, in order of first appearance.
+/// Scene and act bodies get frames of their own and are not searched.
+fn assigned(statements: &[Stmt], names: &mut Vec<String>) {
+    fn add(name: &str, names: &mut Vec<String>) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    fn expression(expr: &Expr, names: &mut Vec<String>) {
+        match expr {
+            Expr::Entangle(var1, _) => add(var1, names),
+            Expr::BinaryOp { left, right, .. } => {
+                expression(left, names);
+                expression(right, names);
+            }
+            Expr::Call { args: exprs, .. } | Expr::Superpose(exprs) => {
+                for e in exprs {
+                    expression(e, names);
+                }
+            }
+            Expr::Rewrite { target: expr }
+            | Expr::Collapse(expr)
+            | Expr::Rift(expr)
+            | Expr::Before(_, expr)
+            | Expr::During(_, expr) => expression(expr, names),
+            _ => {}
+        }
+    }
+
+    for stmt in statements {
+        match &stmt.kind {
+            StmtKind::Mask { name, value, .. } => {
+                expression(value, names);
+                add(name, names);
+            }
+            StmtKind::Act { name, .. } => add(name, names),
+            StmtKind::Rewrite { target } => add(target, names),
+            StmtKind::Echo(expr) | StmtKind::ExprStmt(expr) | StmtKind::Carcosa(Some(expr)) | StmtKind::Assert(expr) => {
+                expression(expr, names)
+            }
+            StmtKind::Remember { value: Some(expr), .. } => expression(expr, names),
+            StmtKind::Hastur { condition, body, .. } => {
+                expression(condition, names);
+                assigned(body, names);
+            }
+            StmtKind::Cassilda { condition, then_branch, else_branch } => {
+                expression(condition, names);
+                assigned(then_branch, names);
+                if let Some(else_branch) = else_branch {
+                    assigned(else_branch, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lexer, Parser, StandardPolicy};
+
+    fn resolved(source: &str, globals: &BTreeMap<String, Value>) -> (Vec<Stmt>, Vec<(Span, String)>) {
+        let mut program = Parser::with_policy(Lexer::new(source).tokenize(), &StandardPolicy).parse_program().unwrap();
+        let diagnostics = resolve(&mut program, globals);
+        (program, diagnostics)
+    }
+
+    fn mask(stmt: &Stmt) -> (Slot, &Expr) {
+        match &stmt.kind {
+            StmtKind::Mask { slot, value, .. } => (*slot, value),
+            kind => panic!("expected a mask, got {:?}", kind),
+        }
+    }
+
+    fn read(expr: &Expr) -> Slot {
+        match expr {
+            Expr::Identifier(_, slot) => *slot,
+            Expr::BinaryOp { left, .. } => read(left),
+            expr => panic!("expected a read, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn scene_names_get_slots_in_order_and_globals_stay_dynamic() {
+        let source = "mask g -> 1;\nscene {\n    mask a -> g;\n    mask b -> a + 1;\n}\n";
+        let (program, diagnostics) = resolved(source, &BTreeMap::new());
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(mask(&program[0]).0, Slot::Dynamic);
+        let StmtKind::Scene { body, locals } = &program[1].kind else { panic!("expected a scene") };
+        assert_eq!(&locals[..], ["a", "b"]);
+        assert_eq!(mask(&body[0]).0, Slot::Local(0));
+        assert_eq!(read(mask(&body[0]).1), Slot::Dynamic);
+        assert_eq!(mask(&body[1]).0, Slot::Local(1));
+        assert_eq!(read(mask(&body[1]).1), Slot::Local(0));
+    }
+
+    #[test]
+    fn act_parameters_come_before_the_names_the_body_assigns() {
+        let (program, _) = resolved("act f(n, m) {\n    mask total -> n + m;\n    Carcosa total;\n}\n", &BTreeMap::new());
+        let StmtKind::Act { act, .. } = &program[0].kind else { panic!("expected an act") };
+        assert_eq!(&act.locals[..], ["n", "m", "total"]);
+        assert_eq!(mask(&act.body[0]).0, Slot::Local(2));
+        assert_eq!(read(mask(&act.body[0]).1), Slot::Local(0));
+    }
+
+    #[test]
+    fn reports_reads_nothing_defines_once() {
+        let (_, diagnostics) = resolved("echo(ghost);\necho(ghost);\necho(vanish(1));\n", &BTreeMap::new());
+        let messages: Vec<_> = diagnostics.iter().map(|(span, message)| (span.line, message.as_str())).collect();
+        assert_eq!(
+            messages,
+            [
+                (1, "Undefined: 'ghost' is read before anything masks it"),
+                (3, "Unknown act: 'vanish' is called before it is defined"),
+            ]
+        );
+    }
+
+    #[test]
+    fn earlier_runs_later_masks_and_whispers_excuse_a_read() {
+        let globals = BTreeMap::from([("old".to_string(), Value::Null)]);
+        let source = "echo(old);\nact f() {\n    Carcosa later;\n}\nmask later -> 1;\nwhisper \"mask w -> 1;\";\necho(w);\n";
+        let (_, diagnostics) = resolved(source, &globals);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+}
@@ -0,0 +1,338 @@
+// ============================================================================
+// SNAPSHOTS
+// ============================================================================
+//
+// The interpreter's whole memory as a JSON document, and back again. Acts are
+// stored as source and re-parsed on restore, since a snapshot has to outlive
+// the syntax tree it was taken from. Settings such as the sanity policy,
+// lucid mode and limits belong to the host, not the session, and are left out,
+// and so are the built-in acts: a restored session keeps the ones it has.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+use crate::json::{self, Json, Object};
+use crate::{
+    builtins, printer, resolver, ActDef, Infection, Interpreter, Lexer, Parser, QuantumState, StandardPolicy, Stmt, StmtKind,
+    TemporalEcho, Value,
+};
+
+/// Bumped whenever the layout changes, so stale saves are refused rather than misread.
+const VERSION: u64 = 1;
+
+fn value(val: &Value) -> String {
+    match val {
+        Value::Number(n) if n.is_finite() => json::number(*n),
+        Value::Number(n) => Object::new().string("kind", "number").string("value", &n.to_string()).finish(),
+        Value::String(s) => json::quote(s),
+        Value::Boolean(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        Value::Function { act, .. } => Object::new()
+            .string("kind", "act")
+            .raw("params", &json::array(act.params().iter().map(|p| json::quote(p))))
+            .string("body", &printer::statements(&act.body, 1))
+            .finish(),
+        Value::Native(native) => Object::new().string("kind", "native").string("name", native.name()).finish(),
+        Value::History(vals) => {
+            Object::new().string("kind", "history").raw("values", &json::array(vals.iter().map(value))).finish()
+        }
+        Value::Quantum(qs) => match &**qs {
+            QuantumState::Superposition(states) => Object::new()
+                .string("kind", "superposition")
+                .raw("states", &json::array(states.iter().map(value)))
+                .finish(),
+            QuantumState::Entangled(name) => Object::new().string("kind", "entangled").string("with", name).finish(),
+            QuantumState::Phantom => Object::new().string("kind", "phantom").finish(),
+        },
+    }
+}
+
+fn environment<'a, I: IntoIterator<Item = (&'a String, &'a Value)>>(vars: I) -> String {
+    vars.into_iter().fold(Object::new(), |object, (name, val)| object.raw(name, &value(val))).finish()
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.get(key).ok_or_else(|| format!("Snapshot is missing '{}'", key))
+}
+
+fn malformed(what: &str) -> String {
+    format!("Snapshot has a malformed {}", what)
+}
+
+fn read_act(params: &Json, body: &str) -> Result<Rc<ActDef>, String> {
+    let params = params
+        .as_array()
+        .and_then(|params| params.iter().map(|p| p.as_str()).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| malformed("act"))?;
+    let source = format!("act restored({}) {{\n{}}}", params.join(", "), body);
+    let tokens = Lexer::new(&source).tokenize();
+    let mut ast = Parser::with_policy(tokens, &StandardPolicy)
+        .parse_program()
+        .map_err(|e| format!("Snapshot act does not parse: {}", e))?;
+    resolver::resolve(&mut ast, &BTreeMap::new());
+    match ast.pop() {
+        Some(Stmt { kind: StmtKind::Act { act, .. }, .. }) if ast.is_empty() => Ok(act),
+        _ => Err(malformed("act")),
+    }
+}
+
+/// Reads a value back. A native act is found among `natives`, the acts the
+/// restoring interpreter has installed, or else among the built-in ones.
+fn read_value(json: &Json, natives: &BTreeMap<String, Value>) -> Result<Value, String> {
+    let kind = match json {
+        Json::Null => return Ok(Value::Null),
+        Json::Bool(b) => return Ok(Value::Boolean(*b)),
+        Json::Number(_) => return json.as_f64().map(Value::Number).ok_or_else(|| malformed("number")),
+        Json::String(s) => return Ok(Value::String(s.clone())),
+        Json::Array(_) => return Err(malformed("value")),
+        Json::Object(_) => field(json, "kind")?.as_str().ok_or_else(|| malformed("value"))?,
+    };
+    let quantum = |state| Ok(Value::Quantum(Box::new(state)));
+    match kind {
+        "number" => field(json, "value")?.as_real().map(Value::Number).ok_or_else(|| malformed("number")),
+        "act" => {
+            let body = field(json, "body")?.as_str().ok_or_else(|| malformed("act"))?;
+            Ok(Value::Function { act: read_act(field(json, "params")?, body)? })
+        }
+        "native" => {
+            let name = field(json, "name")?.as_str().ok_or_else(|| malformed("native act"))?;
+            natives
+                .get(name)
+                .cloned()
+                .or_else(|| builtins::lookup(name))
+                .ok_or_else(|| format!("Snapshot holds an unknown native act '{}'", name))
+        }
+        "history" => {
+            let vals = field(json, "values")?.as_array().ok_or_else(|| malformed("history"))?;
+            Ok(Value::History(vals.iter().map(|val| read_value(val, natives)).collect::<Result<_, _>>()?))
+        }
+        "superposition" => {
+            let states = field(json, "states")?.as_array().ok_or_else(|| malformed("superposition"))?;
+            quantum(QuantumState::Superposition(states.iter().map(|state| read_value(state, natives)).collect::<Result<_, _>>()?))
+        }
+        "entangled" => {
+            let with = field(json, "with")?.as_str().ok_or_else(|| malformed("entanglement"))?;
+            quantum(QuantumState::Entangled(with.to_string()))
+        }
+        "phantom" => quantum(QuantumState::Phantom),
+        other => Err(format!("Snapshot has a value of unknown kind '{}'", other)),
+    }
+}
+
+fn read_environment(json: &Json, natives: &BTreeMap<String, Value>) -> Result<BTreeMap<String, Value>, String> {
+    let vars = json.as_object().ok_or_else(|| malformed("environment"))?;
+    vars.iter().map(|(name, val)| Ok((name.clone(), read_value(val, natives)?))).collect()
+}
+
+fn read_fragments(json: &Json, natives: &BTreeMap<String, Value>) -> Result<BTreeMap<String, VecDeque<Value>>, String> {
+    let fragments = json.as_object().ok_or_else(|| malformed("fragment store"))?;
+    fragments
+        .iter()
+        .map(|(name, values)| {
+            let values = values.as_array().ok_or_else(|| malformed("fragment store"))?;
+            Ok((name.clone(), values.iter().map(|val| read_value(val, natives)).collect::<Result<_, _>>()?))
+        })
+        .collect()
+}
+
+fn read_echo(json: &Json, natives: &BTreeMap<String, Value>) -> Result<TemporalEcho, String> {
+    Ok(TemporalEcho {
+        timestamp: field(json, "timestamp")?.as_u64().ok_or_else(|| malformed("echo"))?,
+        variable_name: field(json, "name")?.as_str().ok_or_else(|| malformed("echo"))?.to_string(),
+        ghost_value: Box::new(read_value(field(json, "value")?, natives)?),
+        stability: field(json, "stability")?.as_real().ok_or_else(|| malformed("echo"))?,
+    })
+}
+
+fn read_infection(json: &Json) -> Result<(String, Infection), String> {
+    let name = field(json, "name")?.as_str().ok_or_else(|| malformed("infection"))?;
+    let infection = Infection {
+        source: field(json, "source")?.as_str().ok_or_else(|| malformed("infection"))?.to_string(),
+        virulence: field(json, "virulence")?.as_real().ok_or_else(|| malformed("infection"))?,
+        mutation_vector: field(json, "mutation_vector")?.as_u64().ok_or_else(|| malformed("infection"))?,
+    };
+    Ok((name.to_string(), infection))
+}
+
+impl Interpreter {
+    /// Everything a session has accumulated, between runs. Integers are written
+    /// exactly, even past the 2^53 that JavaScript numbers can hold.
+    pub(crate) fn snapshot(&self) -> String {
+        let echoes = self.temporal_echoes.iter().map(|echo| {
+            Object::new()
+                .raw("timestamp", &echo.timestamp.to_string())
+                .string("name", &echo.variable_name)
+                .raw("value", &value(&echo.ghost_value))
+                .raw("stability", &json::real(echo.stability))
+                .finish()
+        });
+        let infections = self.infections.iter().map(|(name, infection)| {
+            Object::new()
+                .string("name", name)
+                .string("source", &infection.source)
+                .raw("virulence", &json::real(infection.virulence))
+                .raw("mutation_vector", &infection.mutation_vector.to_string())
+                .finish()
+        });
+        let fragments = self
+            .memory_fragments
+            .iter()
+            .fold(Object::new(), |object, (name, fragments)| {
+                object.raw(name, &json::array(fragments.iter().map(value)))
+            })
+            .finish();
+        Object::new()
+            .raw("version", &VERSION.to_string())
+            .raw("sanity", &json::real(self.sanity))
+            .raw("sanity_seen", &json::real(self.sanity_seen))
+            .raw("entropy", &self.entropy.to_string())
+            .raw("rng", &self.rng.state().to_string())
+            .raw("reality_stable", &self.reality_stable.to_string())
+            .raw("whisper_count", &self.whisper_count.to_string())
+            .raw("globals", &environment(self.own_globals()))
+            .raw("fragments", &fragments)
+            .raw("echoes", &json::array(echoes))
+            .raw("infections", &json::array(infections))
+            .raw("phantoms", &environment(&self.phantom_variables))
+            .raw("generated_code", &json::array(self.generated_code.iter().map(|code| json::quote(code))))
+            .finish()
+    }
+
+    /// Replaces the session with a snapshot. Nothing changes unless the whole snapshot reads cleanly.
+    pub(crate) fn restore(&mut self, snapshot: &str) -> Result<(), String> {
+        let json = json::parse(snapshot).map_err(|e| format!("Snapshot is not valid JSON: {}", e))?;
+        self.restore_json(&json)
+    }
+
+    pub(crate) fn restore_json(&mut self, json: &Json) -> Result<(), String> {
+        match field(json, "version")?.as_u64() {
+            Some(VERSION) => {}
+            _ => return Err("Snapshot was written by an incompatible version".to_string()),
+        }
+        let sanity = field(json, "sanity")?.as_real().ok_or_else(|| malformed("sanity"))?;
+        let sanity_seen = field(json, "sanity_seen")?.as_real().ok_or_else(|| malformed("sanity"))?;
+        let entropy = field(json, "entropy")?.as_u64().ok_or_else(|| malformed("entropy"))?;
+        let rng = field(json, "rng")?.as_u64().ok_or_else(|| malformed("RNG state"))?;
+        let reality_stable = field(json, "reality_stable")?.as_bool().ok_or_else(|| malformed("reality flag"))?;
+        let whisper_count = field(json, "whisper_count")?.as_u64().ok_or_else(|| malformed("whisper count"))?;
+        let natives = self.natives();
+        let mut global_env = read_environment(field(json, "globals")?, &natives)?;
+        let memory_fragments = read_fragments(field(json, "fragments")?, &natives)?;
+        let temporal_echoes = field(json, "echoes")?
+            .as_array()
+            .ok_or_else(|| malformed("echo list"))?
+            .iter()
+            .map(|echo| read_echo(echo, &natives))
+            .collect::<Result<VecDeque<_>, _>>()?;
+        let infections = field(json, "infections")?
+            .as_array()
+            .ok_or_else(|| malformed("infection list"))?
+            .iter()
+            .map(read_infection)
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        let phantom_variables = read_environment(field(json, "phantoms")?, &natives)?;
+        let generated_code = field(json, "generated_code")?
+            .as_array()
+            .and_then(|code| code.iter().map(|c| c.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| malformed("generated code"))?;
+
+        for (name, val) in natives {
+            global_env.entry(name).or_insert(val);
+        }
+        self.sanity = sanity;
+        self.sanity_seen = sanity_seen;
+        self.entropy = entropy;
+        self.rng.restore(rng);
+        self.reality_stable = reality_stable;
+        self.whisper_count = whisper_count as usize;
+        self.global_env = global_env;
+        self.call_stack.clear();
+        self.execution_depth = 0;
+        self.call_depth = 0;
+        self.memory_fragments = memory_fragments;
+        self.temporal_echoes = temporal_echoes;
+        self.infections = infections;
+        self.phantom_variables = phantom_variables;
+        self.generated_code = generated_code;
+        // The snapshot may predate tighter retention settings.
+        self.trim_echoes();
+        self.trim_fragments();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::YellowWebInterpreter;
+
+    const SESSION: &str = "\
+act double(n) {
+    Carcosa n * 2;
+}
+mask a -> 3;
+mask a -> 5;
+remember a;
+remember a -> \"ash\";
+mask b -> a + 1;
+infect b;
+whisper \"mask w -> 9;\";
+";
+
+    const LATER: &str = "echo(double(a));\necho(b);\necho(w);\necho(oldest(a));\necho(before(a, 1));\necho(superpose(1, 2, 3));\n";
+
+    #[test]
+    fn restores_what_it_took() {
+        let mut original = YellowWebInterpreter::new();
+        original.run_code(SESSION);
+        let taken = original.snapshot().unwrap();
+
+        let mut restored = YellowWebInterpreter::with_seed(42);
+        restored.restore(&taken).unwrap();
+        assert_eq!(restored.snapshot().unwrap(), taken);
+        assert_eq!(restored.get_rng_state(), original.get_rng_state());
+        assert_eq!(restored.run_code(LATER), original.run_code(LATER));
+    }
+
+    #[test]
+    fn host_acts_come_back_from_the_restoring_interpreter() {
+        let host = || {
+            let mut interpreter = YellowWebInterpreter::new();
+            interpreter.register_act("shake", 1, |args| Ok(args[0].clone())).unwrap();
+            interpreter
+        };
+        let mut original = host();
+        original.run_code("mask alias -> shake;\nremember alias;\n");
+        let taken = original.snapshot().unwrap();
+
+        let later = "echo(alias(4));\nmask again -> manifest(alias);\necho(again(5));\n";
+        let mut restored = host();
+        restored.restore(&taken).unwrap();
+        assert_eq!(restored.snapshot().unwrap(), taken);
+        let output = restored.run_code(later);
+        assert!(output.contains("\n4\n") && output.contains("\n5\n"), "{}", output);
+        assert_eq!(output, original.run_code(later));
+        assert!(YellowWebInterpreter::new().restore(&taken).is_err());
+    }
+
+    #[test]
+    fn a_bad_snapshot_changes_nothing() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.run_code(SESSION);
+        let taken = interpreter.snapshot().unwrap();
+        assert!(interpreter.restore("{\"version\": 999}").is_err());
+        assert!(interpreter.restore(&taken.replace("\"sanity\":", "\"sanity\":\"high\",\"x\":")).is_err());
+        assert!(interpreter.restore("not json").is_err());
+        assert_eq!(interpreter.snapshot().unwrap(), taken);
+    }
+
+    #[test]
+    fn only_taken_between_runs() {
+        let mut interpreter = YellowWebInterpreter::new();
+        interpreter.debug(SESSION);
+        assert_eq!(interpreter.step(1), "paused");
+        assert!(interpreter.snapshot().is_err());
+        interpreter.resume("continue").unwrap();
+        assert_eq!(interpreter.step(1000), "finished");
+        assert!(interpreter.snapshot().is_ok());
+    }
+}
@@ -0,0 +1,389 @@
+// ============================================================================
+// TRACING
+// ============================================================================
+//
+// A recorded run keeps every random draw and what each one made happen: the
+// conditions that flipped, the drift numbers took, the states a collapse
+// picked and the phantoms that spawned. A trace holds those next to the
+// source, the sanity policy, the settings and the session the run started
+// from, so a replay can serve the same draws back and reproduce the run
+// exactly, whatever policy the host has chosen since.
+
+use std::rc::Rc;
+
+use crate::json::{self, Json, Object};
+use crate::{EchoRetention, Interpreter, Limits, Rng, SanityCost, SanityPolicy, SanityThreshold};
+
+/// Bumped whenever the layout changes, so stale traces are refused rather than misread.
+const VERSION: u64 = 1;
+
+/// Something a draw (or, for drift, the run's state) made happen.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Effect {
+    /// A condition came out the other way round.
+    Flip { draw: usize, from: bool },
+    /// A number was nudged by temporal drift.
+    Drift { value: f64 },
+    /// A superposition collapsed to state `choice` of `of`.
+    Collapse { draw: usize, choice: usize, of: usize },
+    /// A phantom variable appeared.
+    Phantom { draw: usize, name: String },
+}
+
+impl Effect {
+    fn to_json(&self) -> String {
+        match self {
+            Effect::Flip { draw, from } => Object::new()
+                .string("kind", "flip")
+                .raw("draw", &draw.to_string())
+                .raw("from", &from.to_string())
+                .finish(),
+            Effect::Drift { value } => Object::new().string("kind", "drift").raw("value", &json::real(*value)).finish(),
+            Effect::Collapse { draw, choice, of } => Object::new()
+                .string("kind", "collapse")
+                .raw("draw", &draw.to_string())
+                .raw("choice", &choice.to_string())
+                .raw("of", &of.to_string())
+                .finish(),
+            Effect::Phantom { draw, name } => Object::new()
+                .string("kind", "phantom")
+                .raw("draw", &draw.to_string())
+                .string("name", name)
+                .finish(),
+        }
+    }
+
+    fn read(json: &Json) -> Result<Effect, String> {
+        let index = |key| field(json, key).and_then(|n| n.as_u64().map(|n| n as usize).ok_or_else(|| malformed("effect")));
+        match field(json, "kind")?.as_str() {
+            Some("flip") => Ok(Effect::Flip {
+                draw: index("draw")?,
+                from: field(json, "from")?.as_bool().ok_or_else(|| malformed("effect"))?,
+            }),
+            Some("drift") => Ok(Effect::Drift {
+                value: field(json, "value")?.as_real().ok_or_else(|| malformed("effect"))?,
+            }),
+            Some("collapse") => Ok(Effect::Collapse { draw: index("draw")?, choice: index("choice")?, of: index("of")? }),
+            Some("phantom") => Ok(Effect::Phantom {
+                draw: index("draw")?,
+                name: field(json, "name")?.as_str().ok_or_else(|| malformed("effect"))?.to_string(),
+            }),
+            _ => Err(malformed("effect")),
+        }
+    }
+}
+
+/// The draws and effects of the run being recorded, and the trace header written when it
+/// began. A replay records without a header, only to compare against its trace.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    header: Option<Object>,
+    draws: Vec<f64>,
+    effects: Vec<Effect>,
+}
+
+impl Recorder {
+    pub(crate) fn draw(&mut self, draw: f64) {
+        self.draws.push(draw);
+    }
+
+    /// Records an effect of the latest draw; `effect` is handed that draw's index.
+    pub(crate) fn note(&mut self, effect: impl FnOnce(usize) -> Effect) {
+        let draw = self.draws.len().saturating_sub(1);
+        self.effects.push(effect(draw));
+    }
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.get(key).ok_or_else(|| format!("Trace is missing '{}'", key))
+}
+
+fn malformed(what: &str) -> String {
+    format!("Trace has a malformed {}", what)
+}
+
+/// A policy rebuilt from the costs and thresholds a trace wrote down.
+struct TablePolicy {
+    costs: [f64; 13],
+    thresholds: [f64; 6],
+}
+
+impl SanityPolicy for TablePolicy {
+    fn cost(&self, cost: SanityCost) -> f64 {
+        self.costs[cost as usize]
+    }
+
+    fn threshold(&self, threshold: SanityThreshold) -> f64 {
+        self.thresholds[threshold as usize]
+    }
+}
+
+fn policy_json(policy: &dyn SanityPolicy) -> String {
+    let costs = SanityCost::ALL.iter().fold(Object::new(), |object, &cost| {
+        object.raw(cost.name(), &json::real(policy.cost(cost)))
+    });
+    let thresholds = SanityThreshold::ALL.iter().fold(Object::new(), |object, &threshold| {
+        object.raw(threshold.name(), &json::real(policy.threshold(threshold)))
+    });
+    Object::new().raw("costs", &costs.finish()).raw("thresholds", &thresholds.finish()).finish()
+}
+
+fn read_table<const N: usize>(json: &Json, names: [&str; N]) -> Result<[f64; N], String> {
+    let mut table = [0.0; N];
+    for (entry, name) in table.iter_mut().zip(names) {
+        *entry = field(json, name)?.as_real().ok_or_else(|| malformed("policy"))?;
+    }
+    Ok(table)
+}
+
+fn read_policy(json: &Json) -> Result<TablePolicy, String> {
+    Ok(TablePolicy {
+        costs: read_table(field(json, "costs")?, SanityCost::ALL.map(SanityCost::name))?,
+        thresholds: read_table(field(json, "thresholds")?, SanityThreshold::ALL.map(SanityThreshold::name))?,
+    })
+}
+
+/// Serves a trace's draws back in order. Its state is how many have been served; a replay
+/// that asks for more than were recorded gets zeros and is reported as diverged.
+struct ReplayRng {
+    draws: Vec<f64>,
+    position: usize,
+}
+
+impl Rng for ReplayRng {
+    fn next_f64(&mut self) -> f64 {
+        let draw = self.draws.get(self.position).copied().unwrap_or(0.0);
+        self.position += 1;
+        draw
+    }
+
+    fn state(&self) -> u64 {
+        self.position as u64
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.position = state as usize;
+    }
+}
+
+/// Everything a replay borrows from the host and gives back when it is done.
+pub(crate) struct Host {
+    policy: Rc<dyn SanityPolicy>,
+    rng: Box<dyn Rng>,
+    lucid: bool,
+    limits: Limits,
+    echo_retention: EchoRetention,
+    fragment_capacity: usize,
+}
+
+/// A trace read back in, ready to replay.
+pub(crate) struct Trace {
+    pub(crate) source: String,
+    policy: TablePolicy,
+    lucid: bool,
+    limits: Limits,
+    echo_retention: EchoRetention,
+    fragment_capacity: usize,
+    state: Json,
+    draws: Vec<f64>,
+    effects: Vec<Effect>,
+    transcript: String,
+}
+
+impl Trace {
+    pub(crate) fn parse(text: &str) -> Result<Trace, String> {
+        let json = json::parse(text).map_err(|e| format!("Trace is not valid JSON: {}", e))?;
+        match field(&json, "version")?.as_u64() {
+            Some(VERSION) => {}
+            _ => return Err("Trace was written by an incompatible version".to_string()),
+        }
+        let settings = field(&json, "settings")?;
+        let count = |key| field(settings, key)?.as_u64().ok_or_else(|| malformed("setting"));
+        let retention = field(settings, "echo_retention")?;
+        let mode = field(retention, "mode")?.as_str().ok_or_else(|| malformed("echo retention"))?;
+        let limit = field(retention, "limit")?.as_u64().ok_or_else(|| malformed("echo retention"))?;
+        let echo_retention = match mode {
+            "window" => EchoRetention::Window(limit),
+            mode => EchoRetention::parse(mode, limit as u32)?,
+        };
+        let fuel = match field(settings, "fuel")? {
+            Json::Null => None,
+            fuel => Some(fuel.as_u64().ok_or_else(|| malformed("setting"))?),
+        };
+        let limits = Limits {
+            fuel,
+            max_call_depth: count("max_call_depth")? as usize,
+            max_nesting: count("max_nesting")? as usize,
+            max_loop_iterations: count("max_loop_iterations")? as u32,
+        };
+        let draws = field(&json, "draws")?
+            .as_array()
+            .and_then(|draws| draws.iter().map(Json::as_real).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| malformed("draw list"))?;
+        let effects = field(&json, "effects")?
+            .as_array()
+            .ok_or_else(|| malformed("effect list"))?
+            .iter()
+            .map(Effect::read)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Trace {
+            source: field(&json, "source")?.as_str().ok_or_else(|| malformed("source"))?.to_string(),
+            policy: read_policy(field(&json, "policy")?)?,
+            lucid: field(settings, "lucid")?.as_bool().ok_or_else(|| malformed("setting"))?,
+            limits,
+            echo_retention,
+            fragment_capacity: count("fragment_capacity")? as usize,
+            state: field(&json, "state")?.clone(),
+            draws,
+            effects,
+            transcript: field(&json, "transcript")?.as_str().ok_or_else(|| malformed("transcript"))?.to_string(),
+        })
+    }
+}
+
+impl Interpreter {
+    /// Starts recording a run of `source` from the session as it stands.
+    pub(crate) fn start_recording(&mut self, source: &str) {
+        let (mode, limit) = self.echo_retention.describe();
+        let fuel = self.limits.fuel.map_or_else(|| "null".to_string(), |fuel| fuel.to_string());
+        let settings = Object::new()
+            .raw("lucid", &self.lucid.to_string())
+            .raw("echo_retention", &Object::new().string("mode", mode).raw("limit", &limit.to_string()).finish())
+            .raw("fragment_capacity", &self.fragment_capacity.to_string())
+            .raw("fuel", &fuel)
+            .raw("max_call_depth", &self.limits.max_call_depth.to_string())
+            .raw("max_nesting", &self.limits.max_nesting.to_string())
+            .raw("max_loop_iterations", &self.limits.max_loop_iterations.to_string());
+        let header = Object::new()
+            .raw("version", &VERSION.to_string())
+            .string("source", source)
+            .raw("policy", &policy_json(self.policy.as_ref()))
+            .raw("settings", &settings.finish())
+            .raw("state", &self.snapshot());
+        self.recorder = Some(Box::new(Recorder { header: Some(header), ..Recorder::default() }));
+    }
+
+    /// Stops recording and returns the trace, with `transcript` as what the run printed.
+    pub(crate) fn finish_recording(&mut self, transcript: &str) -> Option<String> {
+        let recorder = self.recorder.take()?;
+        Some(
+            recorder
+                .header?
+                .raw("draws", &json::array(recorder.draws.iter().map(|&draw| json::real(draw))))
+                .raw("effects", &json::array(recorder.effects.iter().map(Effect::to_json)))
+                .string("transcript", transcript)
+                .finish(),
+        )
+    }
+
+    /// Puts the session, policy and settings back as they were when `trace` was recorded,
+    /// and has the RNG serve its draws. Nothing changes if the recorded session does not
+    /// read cleanly.
+    pub(crate) fn begin_replay(&mut self, trace: &Trace) -> Result<Host, String> {
+        self.restore_json(&trace.state)?;
+        let policy = TablePolicy { costs: trace.policy.costs, thresholds: trace.policy.thresholds };
+        let rng = ReplayRng { draws: trace.draws.clone(), position: 0 };
+        let host = Host {
+            policy: std::mem::replace(&mut self.policy, Rc::new(policy)),
+            rng: std::mem::replace(&mut self.rng, Box::new(rng)),
+            lucid: std::mem::replace(&mut self.lucid, trace.lucid),
+            limits: std::mem::replace(&mut self.limits, trace.limits),
+            echo_retention: std::mem::replace(&mut self.echo_retention, trace.echo_retention),
+            fragment_capacity: std::mem::replace(&mut self.fragment_capacity, trace.fragment_capacity),
+        };
+        // Record the replay too, to check it against the recording.
+        self.recorder = Some(Box::default());
+        Ok(host)
+    }
+
+    /// Hands the host its settings back. Returns why the replay strayed from `trace`, if it did.
+    pub(crate) fn end_replay(&mut self, host: Host, trace: &Trace, transcript: &str) -> Option<String> {
+        let recorder = self.recorder.take().unwrap_or_default();
+        self.policy = host.policy;
+        self.rng = host.rng;
+        // The recorded session set the host's generator to where the recording began;
+        // move it on past the draws the replay served, to where the recording ended.
+        for _ in 0..recorder.draws.len() {
+            self.rng.next_f64();
+        }
+        self.lucid = host.lucid;
+        self.limits = host.limits;
+        self.echo_retention = host.echo_retention;
+        self.fragment_capacity = host.fragment_capacity;
+        if recorder.draws.len() > trace.draws.len() {
+            Some(format!(
+                "⚠ The replay ran past its recording: {} draws taken, {} recorded.",
+                recorder.draws.len(),
+                trace.draws.len()
+            ))
+        } else if recorder.draws.len() != trace.draws.len()
+            || recorder.effects != trace.effects
+            || transcript != trace.transcript
+        {
+            Some("⚠ The replay diverged from its recording.".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::YellowWebInterpreter;
+
+    // Every iteration collapses, so the transcript depends on each draw the recording made.
+    const PROGRAM: &str = "\
+mask i -> 0;
+mask heads -> 0;
+Hastur (i < 20) {
+    Cassilda (collapse(superpose(yellow, tattered))) { mask heads -> heads + 1; }
+    mask i -> i + 1;
+}
+echo(heads);
+echo(superpose(1, 2, 3));
+";
+
+    fn recorded() -> (YellowWebInterpreter, String, String) {
+        let mut interpreter = YellowWebInterpreter::with_seed(7);
+        interpreter.set_sanity_policy("nightmare").unwrap();
+        interpreter.set_recording(true);
+        let transcript = interpreter.run_code(PROGRAM);
+        let trace = interpreter.get_trace();
+        (interpreter, transcript, trace)
+    }
+
+    #[test]
+    fn replays_under_another_policy_and_seed() {
+        let (original, transcript, trace) = recorded();
+        let mut host = YellowWebInterpreter::with_seed(99);
+        host.set_sanity_policy("gentle").unwrap();
+        host.set_lucid(true);
+        assert_eq!(host.replay(&trace).unwrap(), transcript);
+        assert_eq!(host.get_sanity(), original.get_sanity());
+        assert_eq!(host.get_rng_state(), original.get_rng_state());
+        assert!(host.is_lucid());
+    }
+
+    #[test]
+    fn the_host_policy_returns_after_a_replay() {
+        let (mut original, _, trace) = recorded();
+        let mut host = YellowWebInterpreter::with_seed(99);
+        host.set_sanity_policy("gentle").unwrap();
+        host.replay(&trace).unwrap();
+        let before = host.get_sanity();
+        host.run_code("mask x -> 1;");
+        original.run_code("mask x -> 1;");
+        assert!(before - host.get_sanity() < before - original.get_sanity());
+    }
+
+    #[test]
+    fn a_tampered_trace_is_reported_as_diverged() {
+        let (_, transcript, trace) = recorded();
+        let tampered = trace.replace("echo(heads);", "echo(heads + 1);");
+        let mut host = YellowWebInterpreter::new();
+        assert_ne!(tampered, trace);
+        let replayed = host.replay(&tampered).unwrap();
+        assert_ne!(replayed, transcript);
+        assert!(replayed.contains("⚠ The replay"), "{}", replayed);
+    }
+}
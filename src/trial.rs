@@ -0,0 +1,123 @@
+// ============================================================================
+// TRIALS
+// ============================================================================
+//
+// Yellow code testing itself. Ordinary runs pass over `trial` blocks; the
+// runner here gives each top-level trial a fresh interpreter, runs the rest
+// of the program in it as the setup, then the trial's body, and reports
+// whether the body got through without a runtime horror (a failed `assert`
+// among them) and how much sanity it cost.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::events::Events;
+use crate::json::{self, Object};
+use crate::{builtins, compiler, resolver, Engine, Interpreter, Lexer, Parser, SanityPolicy, StandardPolicy, Stmt, StmtKind, Value};
+
+/// How one trial went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrialOutcome {
+    pub name: String,
+    /// Why it failed; `None` when it passed.
+    pub failure: Option<String>,
+    /// Sanity the trial's own body drained, in percentage points. The setup is not counted.
+    pub sanity_consumed: f64,
+    /// What the body printed.
+    pub output: String,
+}
+
+impl TrialOutcome {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    fn to_json(&self) -> String {
+        Object::new()
+            .string("name", &self.name)
+            .raw("passed", &self.passed().to_string())
+            .raw("failure", &self.failure.as_deref().map_or("null".to_string(), json::quote))
+            .number("sanity_consumed", self.sanity_consumed)
+            .string("output", &self.output)
+            .finish()
+    }
+}
+
+/// `[{"name", "passed", "failure", "sanity_consumed", "output"}]`, in source order.
+pub(crate) fn to_json(outcomes: &[TrialOutcome]) -> String {
+    json::array(outcomes.iter().map(TrialOutcome::to_json))
+}
+
+impl Interpreter {
+    fn run_program(&mut self, program: &[Stmt], engine: Engine, buffer: &mut Events) -> Result<Option<Value>, String> {
+        match engine {
+            Engine::Bytecode => self.run_compiled(Rc::new(compiler::compile(program)), buffer),
+            Engine::TreeWalk => self.execute(program, buffer),
+        }
+    }
+}
+
+/// Runs every top-level trial in `source`, in source order, each in a fresh interpreter
+/// seeded with `seed` under the standard policy. Fails only if `source` does not parse.
+pub fn run_trials(source: &str, seed: u64, engine: Engine) -> Result<Vec<TrialOutcome>, String> {
+    run_trials_with(source, seed, engine, &builtins::globals(), &(Rc::new(StandardPolicy) as Rc<dyn SanityPolicy>))
+}
+
+/// `run_trials` with each fresh interpreter starting from `natives` instead of the
+/// built-in acts alone, so trials can call the acts a host registered, and costing
+/// sanity as `policy` does.
+pub(crate) fn run_trials_with(
+    source: &str,
+    seed: u64,
+    engine: Engine,
+    natives: &BTreeMap<String, Value>,
+    policy: &Rc<dyn SanityPolicy>,
+) -> Result<Vec<TrialOutcome>, String> {
+    let mut parser = Parser::with_policy(Lexer::new(source).tokenize(), policy.as_ref());
+    let mut program = parser.parse_program().map_err(|e| format!("Line {}: {}", parser.span().line, e))?;
+    resolver::resolve(&mut program, natives);
+    let (trials, setup): (Vec<Stmt>, Vec<Stmt>) =
+        program.into_iter().partition(|stmt| matches!(stmt.kind, StmtKind::Trial { .. }));
+    Ok(trials
+        .iter()
+        .filter_map(|trial| match &trial.kind {
+            StmtKind::Trial { name, body } => Some(run_trial(name, body, &setup, seed, engine, natives, policy)),
+            _ => None,
+        })
+        .collect())
+}
+
+fn run_trial(
+    name: &str,
+    body: &[Stmt],
+    setup: &[Stmt],
+    seed: u64,
+    engine: Engine,
+    natives: &BTreeMap<String, Value>,
+    policy: &Rc<dyn SanityPolicy>,
+) -> TrialOutcome {
+    let mut interp = Interpreter::with_seed(seed);
+    interp.global_env = natives.clone();
+    interp.policy = Rc::clone(policy);
+    let mut buffer = Events::default();
+    if let Err(e) = interp.run_program(setup, engine, &mut buffer) {
+        buffer.finish();
+        return TrialOutcome {
+            name: name.to_string(),
+            failure: Some(format!("The program failed before the trial began: {}", e)),
+            sanity_consumed: 0.0,
+            output: buffer.render_text(),
+        };
+    }
+
+    let before = interp.sanity;
+    let mut buffer = Events::default();
+    let result = interp.run_program(body, engine, &mut buffer);
+    buffer.finish();
+    TrialOutcome {
+        name: name.to_string(),
+        failure: result.err(),
+        sanity_consumed: before - interp.sanity,
+        output: buffer.render_text(),
+    }
+}
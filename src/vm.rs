@@ -0,0 +1,437 @@
+// ============================================================================
+// VIRTUAL MACHINE
+// ============================================================================
+//
+// Runs chunks from `compiler.rs` against the same `Interpreter` state the
+// tree-walker uses. Every instruction defers to the interpreter's shared
+// helpers, so sanity, infection and quantum behaviour (down to the order of
+// random draws) match the tree-walker exactly.
+
+use std::rc::Rc;
+
+use crate::builtins::Native;
+use crate::compiler::{Chunk, Op};
+use crate::debugger::Debugger;
+use crate::events::Events;
+use crate::{ActDef, Interpreter, SanityCost, Scope, Slot, Value};
+
+/// What a `Callee` instruction resolved.
+enum Callee {
+    /// An act's body, with the name it was called by.
+    Act(Rc<Chunk>, Rc<ActDef>, u32),
+    Native(Rc<Native>),
+}
+
+enum LoopState {
+    Hastur { iterations: u32 },
+    Rift { paradox: i32, completed: i32 },
+}
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    /// Value stack height when the frame was entered.
+    stack_base: usize,
+    /// `call_stack` height before the frame's scope was pushed.
+    scope_base: usize,
+    /// Caller's `execution_depth`, restored when the frame returns.
+    depth_base: usize,
+    loops: Vec<LoopState>,
+    /// Acts resolved by `Callee` whose arguments are still being evaluated.
+    callees: Vec<Callee>,
+    /// The act's name in the caller's chunk; meaningless for the outermost frame.
+    called_as: u32,
+}
+
+impl Frame {
+    fn new(chunk: Rc<Chunk>, stack_base: usize, scope_base: usize, depth_base: usize, called_as: u32) -> Self {
+        Frame { chunk, ip: 0, stack_base, scope_base, depth_base, loops: Vec::new(), callees: Vec::new(), called_as }
+    }
+}
+
+/// A compiled program in flight. It can stop between any two instructions and pick up later.
+pub(crate) struct Machine {
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    /// Interpreter state to put back if the run fails or is abandoned.
+    scope_base: usize,
+    depth_base: usize,
+    call_base: usize,
+}
+
+pub(crate) enum Progress {
+    Finished(Option<Value>),
+    Suspended,
+    /// The debugger stopped before a statement.
+    Paused,
+}
+
+/// One frame of a paused machine, outermost first.
+pub(crate) struct FrameInfo {
+    /// `None` for the program itself.
+    pub(crate) act: Option<String>,
+    pub(crate) line: u32,
+    /// The part of `call_stack` that belongs to this frame.
+    pub(crate) scopes: std::ops::Range<usize>,
+}
+
+impl Interpreter {
+    pub(crate) fn run_compiled(&mut self, chunk: Rc<Chunk>, buffer: &mut Events) -> Result<Option<Value>, String> {
+        let mut machine = Machine::new(self, chunk);
+        match machine.advance(self, buffer, usize::MAX, None)? {
+            Progress::Finished(value) => Ok(value),
+            // Only a budget of usize::MAX instructions could run out here, and nothing is debugging.
+            Progress::Suspended | Progress::Paused => Ok(None),
+        }
+    }
+
+    /// Looks up the act `name`, called by the name at `idx`, without cloning its body.
+    fn resolve_act(&self, idx: u32, name: &str, argc: usize) -> Result<Callee, String> {
+        match self.lookup(name) {
+            Some(Value::Function { act }) => {
+                if act.arity != argc {
+                    return Err("Arity mismatch".to_string());
+                }
+                Ok(Callee::Act(act.chunk(), act.clone(), idx))
+            }
+            Some(Value::Native(native)) => {
+                if native.arity() != argc {
+                    return Err("Arity mismatch".to_string());
+                }
+                Ok(Callee::Native(native.clone()))
+            }
+            Some(_) => Err(format!("{} is not callable", name)),
+            None => Err(format!("Unknown act: {}", name)),
+        }
+    }
+}
+
+impl Machine {
+    pub(crate) fn new(interp: &Interpreter, chunk: Rc<Chunk>) -> Self {
+        Machine {
+            frames: vec![Frame::new(chunk, 0, interp.call_stack.len(), interp.execution_depth, 0)],
+            stack: Vec::new(),
+            scope_base: interp.call_stack.len(),
+            depth_base: interp.execution_depth,
+            call_base: interp.call_depth,
+        }
+    }
+
+    /// Runs at most `budget` instructions, stopping early wherever `debugger` asks to.
+    pub(crate) fn advance(
+        &mut self,
+        interp: &mut Interpreter,
+        buffer: &mut Events,
+        budget: usize,
+        debugger: Option<&mut Debugger>,
+    ) -> Result<Progress, String> {
+        let result = self.run(interp, buffer, budget, debugger);
+        if result.is_err() {
+            self.unwind(interp);
+        }
+        result
+    }
+
+    /// Drops every frame this run pushed onto the interpreter.
+    pub(crate) fn unwind(&mut self, interp: &mut Interpreter) {
+        interp.call_stack.truncate(self.scope_base);
+        interp.execution_depth = self.depth_base;
+        interp.call_depth = self.call_base;
+        self.frames.clear();
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("the machine only runs while a frame is active")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Null)
+    }
+
+    /// Unwinds the current frame. Returns the program's result once the outermost frame is gone.
+    fn return_from(&mut self, interp: &mut Interpreter, value: Option<Value>) -> Option<Option<Value>> {
+        let frame = self.frames.pop()?;
+        interp.call_stack.truncate(frame.scope_base);
+        self.stack.truncate(frame.stack_base);
+        if self.frames.is_empty() {
+            interp.execution_depth = frame.depth_base;
+            return Some(value);
+        }
+        interp.leave_call(frame.depth_base);
+        self.stack.push(value.unwrap_or(Value::Null));
+        None
+    }
+
+    /// The frames from the program down to the innermost act, with the line each is on.
+    pub(crate) fn backtrace(&self, interp: &Interpreter) -> Vec<FrameInfo> {
+        let mut frames = Vec::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let act = i.checked_sub(1).map(|caller| self.frames[caller].chunk.names[frame.called_as as usize].clone());
+            let scopes_end = self.frames.get(i + 1).map_or(interp.call_stack.len(), |callee| callee.scope_base);
+            // Callers have moved past their `Call`; the innermost frame is paused on an `Enter`.
+            let ip = if i + 1 < self.frames.len() { frame.ip.saturating_sub(1) } else { frame.ip };
+            let line = frame.chunk.lines.get(ip).or(frame.chunk.lines.last()).copied().unwrap_or(0);
+            frames.push(FrameInfo { act, line, scopes: frame.scope_base.min(scopes_end)..scopes_end });
+        }
+        frames
+    }
+
+    /// The running frame's chunk and instruction pointer, cached while it executes.
+    fn current(&self) -> (Rc<Chunk>, usize) {
+        let frame = self.frames.last().expect("the machine only runs while a frame is active");
+        (frame.chunk.clone(), frame.ip)
+    }
+
+    fn run(
+        &mut self,
+        interp: &mut Interpreter,
+        buffer: &mut Events,
+        budget: usize,
+        mut debugger: Option<&mut Debugger>,
+    ) -> Result<Progress, String> {
+        if self.frames.is_empty() {
+            return Ok(Progress::Finished(None));
+        }
+        let (mut chunk, mut ip) = self.current();
+        for _ in 0..budget {
+            let Some(&op) = chunk.code.get(ip) else {
+                if let Some(result) = self.return_from(interp, None) {
+                    return Ok(Progress::Finished(result));
+                }
+                (chunk, ip) = self.current();
+                continue;
+            };
+            ip += 1;
+            let name = |idx: u32| chunk.names[idx as usize].as_str();
+
+            match op {
+                Op::Enter => {
+                    if let Some(debugger) = debugger.as_deref_mut() {
+                        if debugger.should_pause(chunk.lines[ip - 1], self.frames.len()) {
+                            self.frame().ip = ip - 1;
+                            return Ok(Progress::Paused);
+                        }
+                    }
+                    interp.tick(buffer)?;
+                    interp.enter_stmt()?;
+                }
+                Op::Leave => interp.execution_depth -= 1,
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    if let Some(result) = self.return_from(interp, Some(value)) {
+                        return Ok(Progress::Finished(result));
+                    }
+                    (chunk, ip) = self.current();
+                }
+                Op::Jump(target) => ip = target as usize,
+                Op::Mask(idx) => {
+                    let val = self.pop();
+                    interp.set_var(name(idx), val);
+                }
+                Op::MaskLocal(slot, idx) => {
+                    let val = self.pop();
+                    interp.assign(Slot::Local(slot), name(idx), val);
+                }
+                Op::Echo => {
+                    let val = self.pop();
+                    interp.echo_value(val, buffer);
+                }
+                Op::PushScope(layout) => {
+                    let names = chunk.layouts[layout as usize].clone();
+                    interp.call_stack.push(Scope::new(names));
+                }
+                Op::PopScope => {
+                    interp.call_stack.pop();
+                }
+                Op::LoopEnter(is_rift) => {
+                    let state = if !is_rift {
+                        LoopState::Hastur { iterations: 0 }
+                    } else {
+                        if interp.lucid {
+                            LoopState::Hastur { iterations: 0 }
+                        } else {
+                            buffer.warn("⚠ Non-Euclidean loop: space folds upon itself");
+                            LoopState::Rift { paradox: interp.paradox_iterations(), completed: 0 }
+                        }
+                    };
+                    self.frame().loops.push(state);
+                }
+                Op::LoopGuard(exit) => {
+                    if let Some(LoopState::Rift { paradox, completed }) = self.frame().loops.last() {
+                        if completed >= paradox {
+                            ip = exit as usize;
+                        }
+                    }
+                }
+                Op::LoopTest(exit) => {
+                    let cond_val = self.pop();
+                    let continues = match self.frame().loops.last() {
+                        Some(LoopState::Rift { .. }) => interp.rift_continues(&cond_val),
+                        _ => interp.is_truthy(&cond_val),
+                    };
+                    if !continues {
+                        ip = exit as usize;
+                    }
+                }
+                Op::LoopNext(top) => {
+                    match self.frame().loops.last_mut() {
+                        Some(LoopState::Hastur { iterations }) => {
+                            let overflowed = interp.loop_overflowed(iterations, buffer);
+                            if !overflowed {
+                                ip = top as usize;
+                            }
+                        }
+                        Some(LoopState::Rift { completed, .. }) => {
+                            interp.drain_sanity(SanityCost::RiftIteration);
+                            *completed += 1;
+                            ip = top as usize;
+                        }
+                        None => {}
+                    }
+                }
+                Op::LoopExit => {
+                    if let Some(LoopState::Rift { paradox, .. }) = self.frame().loops.pop() {
+                        buffer.warn(format!("⚠ Rift loop completed {} iterations", paradox));
+                    }
+                }
+                Op::Branch(else_target) => {
+                    let cond_val = self.pop();
+                    if !interp.take_branch(&cond_val) {
+                        ip = else_target as usize;
+                    }
+                }
+                Op::Act(idx) => {
+                    let proto = &chunk.acts[idx as usize];
+                    let val = Value::Function { act: proto.act.clone() };
+                    interp.set_var(name(proto.name), val);
+                }
+                Op::Rewrite(idx) => interp.rewrite_var(name(idx), buffer),
+                Op::Remember(idx) => {
+                    if let Some(val) = interp.get_var(name(idx)) {
+                        interp.remember(name(idx).to_string(), val);
+                    }
+                }
+                Op::RememberValue(idx) => {
+                    let val = self.pop();
+                    interp.remember(name(idx).to_string(), val);
+                }
+                Op::Purge(idx) => interp.purge(name(idx), buffer),
+                Op::Forget(idx) => interp.forget(name(idx), buffer),
+                Op::Infect(idx) => interp.infect(name(idx), buffer),
+                Op::Whisper(idx) => {
+                    if let Some(val) = interp.whisper(chunk.text(idx), buffer)? {
+                        if let Some(result) = self.return_from(interp, Some(val)) {
+                            return Ok(Progress::Finished(result));
+                        }
+                        (chunk, ip) = self.current();
+                    }
+                }
+                Op::Anchor => interp.anchor(buffer),
+                Op::Assert(text) => {
+                    let val = self.pop();
+                    interp.assert_value(chunk.text(text), val)?;
+                }
+                Op::AssertBinary(op, text) => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    interp.assert_binary(chunk.text(text), l, op, r, buffer)?;
+                }
+
+                Op::Number(idx) => self.stack.push(interp.number(chunk.numbers[idx as usize])),
+                Op::Constant(idx) => self.stack.push(chunk.constants[idx as usize].clone()),
+                Op::Null => self.stack.push(Value::Null),
+                Op::Load(idx) => {
+                    let val = interp.load_var(Slot::Dynamic, name(idx))?;
+                    self.stack.push(val);
+                }
+                Op::LoadLocal(slot, idx) => {
+                    let val = interp.load_var(Slot::Local(slot), name(idx))?;
+                    self.stack.push(val);
+                }
+                Op::Binary(op) => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let val = interp.binary(l, op, r, buffer)?;
+                    self.stack.push(val);
+                }
+                Op::Callee(idx, argc) => {
+                    let callee = interp.resolve_act(idx, name(idx), argc as usize)?;
+                    self.frame().callees.push(callee);
+                }
+                Op::Call(argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    match self.frame().callees.pop() {
+                        Some(Callee::Act(body, act, called_as)) => {
+                            let mut scope = Scope::new(act.locals.clone());
+                            for (param, arg) in act.params().iter().zip(args) {
+                                scope.store(Slot::Dynamic, param, arg);
+                            }
+                            let depth_base = interp.enter_call()?;
+                            let scope_base = interp.call_stack.len();
+                            interp.call_stack.push(scope);
+                            self.frame().ip = ip;
+                            let frame = Frame::new(body, self.stack.len(), scope_base, depth_base, called_as);
+                            self.frames.push(frame);
+                            (chunk, ip) = self.current();
+                        }
+                        Some(Callee::Native(native)) => {
+                            let val = interp.call_native(&native, args, buffer)?;
+                            self.stack.push(val);
+                        }
+                        None => return Err("Call without an act".to_string()),
+                    }
+                }
+                Op::Mutate => {
+                    let val = self.pop();
+                    let val = interp.mutate_value(val);
+                    self.stack.push(val);
+                }
+                Op::Superpose(count) => {
+                    let values = self.stack.split_off(self.stack.len() - count as usize);
+                    let val = interp.superpose(values, buffer);
+                    self.stack.push(val);
+                }
+                Op::Collapse => {
+                    let val = self.pop();
+                    let val = interp.collapse(val, buffer)?;
+                    self.stack.push(val);
+                }
+                Op::Manifest(idx) => {
+                    let val = interp.manifest(name(idx), buffer);
+                    self.stack.push(val);
+                }
+                Op::Peek(idx) => self.stack.push(interp.peek(name(idx))),
+                Op::Oldest(idx) => self.stack.push(interp.oldest(name(idx))),
+                Op::Unearth(idx) => {
+                    let val = interp.unearth(name(idx), buffer);
+                    self.stack.push(val);
+                }
+                Op::Fragments(idx) => self.stack.push(interp.fragment_count(name(idx))),
+                Op::Entangle(var1, var2) => {
+                    let val = interp.entangle(name(var1), name(var2), buffer);
+                    self.stack.push(val);
+                }
+                Op::Rift => interp.rift_expression(buffer),
+                Op::Before(idx) => {
+                    let steps = self.pop();
+                    let val = interp.before(name(idx), steps, buffer)?;
+                    self.stack.push(val);
+                }
+                Op::During(idx) => {
+                    let timestamp = self.pop();
+                    let val = interp.during(name(idx), timestamp, buffer)?;
+                    self.stack.push(val);
+                }
+                Op::Echoes(idx) => {
+                    let val = interp.echoes(name(idx), buffer);
+                    self.stack.push(val);
+                }
+            }
+        }
+        self.frame().ip = ip;
+        Ok(Progress::Suspended)
+    }
+}
@@ -0,0 +1,30 @@
+// Runs every program in tests/golden against its recorded output and final
+// sanity, and every trial in them. After an intended change, re-record them with
+// `YELLOW_BLESS=1 cargo test --test golden` and review the diff.
+
+use std::path::PathBuf;
+
+use the_yellow_compiler::{Golden, Verdict};
+
+#[test]
+fn golden_programs() {
+    let bless = std::env::var_os("YELLOW_BLESS").is_some();
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let goldens = Golden::discover(&[dir]).expect("golden programs");
+    assert!(!goldens.is_empty(), "no golden programs found");
+
+    let mut failures = String::new();
+    for golden in &goldens {
+        match golden.run(bless) {
+            Ok(Verdict::Passed | Verdict::Blessed) => {}
+            Ok(Verdict::Failed(reason)) => failures.push_str(&format!("{}:\n{}\n", golden.source.display(), reason)),
+            Err(e) => failures.push_str(&format!("{}: {}\n", golden.source.display(), e)),
+        }
+        for trial in golden.trials().expect("trials") {
+            if let Some(failure) = trial.failure {
+                failures.push_str(&format!("{}: trial '{}': {}\n", golden.source.display(), trial.name, failure));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures);
+}